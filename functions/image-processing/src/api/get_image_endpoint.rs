@@ -1,17 +1,21 @@
-use super::get_response_image;
-use crate::common::ImageHandlerOptions;
+use super::{get_response_image, get_response_info, RequestOptions};
+use crate::common::{ImageHandlerOptions, InputFormat};
 use crate::error::ResponseError;
-use image::ImageFormat;
+use crate::utils::get_image_from_url;
 use lambda_http::RequestExt;
 use lambda_http::{Body, Error, Request, Response};
-use reqwest::{header, StatusCode};
+use reqwest::StatusCode;
 use serde::Deserialize;
+use serde_aux::prelude::*;
 
 #[derive(Debug, Deserialize)]
 struct GetImageQuery {
     pub source_url: Option<String>,
     pub source_base64: Option<String>,
 
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub info: bool,
+
     #[serde(flatten)]
     pub options: ImageHandlerOptions,
 }
@@ -23,9 +27,7 @@ pub async fn get_image_endpoint(request: Request) -> Result<Response<Body>, Erro
         .query_string_parameters_ref()
         .ok_or_else(|| ResponseError::new(StatusCode::BAD_REQUEST, "missing image query params"))?;
 
-    let query_str = query_map.to_query_string();
-    let mut query: GetImageQuery = serde_qs::from_str(&query_str)
-        .map_err(|e| ResponseError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+    let mut query: GetImageQuery = RequestOptions::from_query(query_map).parse()?;
 
     if query.source_base64.is_none() && query.source_url.is_none() {
         return Err(ResponseError::new(
@@ -43,36 +45,22 @@ pub async fn get_image_endpoint(request: Request) -> Result<Response<Body>, Erro
         .into());
     }
 
-    if let Some(url) = query.source_url.take() {
-        let (buffer, format) = get_image_bytes_from_url(url).await?;
-        get_response_image(buffer, format, query.options).await
+    let (buffer, format) = if let Some(url) = query.source_url.take() {
+        get_image_from_url(url).await?
     } else if let Some(base64) = query.source_base64.take() {
-        let (buffer, format) = get_image_bytes_from_base64(base64).await?;
-        get_response_image(buffer, format, query.options).await
+        get_image_bytes_from_base64(base64).await?
     } else {
         unreachable!()
-    }
-}
+    };
 
-#[tracing::instrument(level = "INFO")]
-async fn get_image_bytes_from_url(url: String) -> Result<(Vec<u8>, ImageFormat), Error> {
-    let res = reqwest::get(url).await?;
-
-    let content_type = res
-        .headers()
-        .get(header::CONTENT_TYPE)
-        .cloned()
-        .ok_or_else(|| Error::from("content type not found"))?;
-
-    let buffer = res.bytes().await?.to_vec();
-    let content_type_str = content_type.to_str().map_err(Error::from)?;
-    let format = ImageFormat::from_mime_type(content_type_str)
-        .ok_or_else(|| Error::from("failed to read format"))?;
+    if query.info {
+        return get_response_info(buffer, format).await;
+    }
 
-    Ok((buffer, format))
+    get_response_image(buffer, format, query.options).await
 }
 
 #[tracing::instrument(level = "INFO")]
-async fn get_image_bytes_from_base64(base64_text: String) -> Result<(Vec<u8>, ImageFormat), Error> {
+async fn get_image_bytes_from_base64(base64_text: String) -> Result<(Vec<u8>, InputFormat), Error> {
     crate::utils::get_image_from_base64(base64_text).await
 }
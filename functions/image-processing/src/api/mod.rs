@@ -1,8 +1,20 @@
 mod get_image_endpoint;
 mod post_image_endpoint;
+mod request_options;
+mod response_batch;
 mod response_image;
+mod response_info;
+mod response_zip;
 
 pub use {
-    get_image_endpoint::get_image_endpoint, post_image_endpoint::post_image_endpoint,
-    response_image::get_response_image,
+    get_image_endpoint::get_image_endpoint,
+    post_image_endpoint::post_image_endpoint,
+    request_options::RequestOptions,
+    response_batch::{
+        get_response_batch, BatchJob, BatchOutput, BatchSource, MAX_BATCH_IMAGES,
+        MAX_CONCURRENT_JOBS,
+    },
+    response_image::{get_response_image, process_outputs, NamedOutput},
+    response_info::get_response_info,
+    response_zip::{ZipResponse, MAX_RESPONSE_BYTES},
 };
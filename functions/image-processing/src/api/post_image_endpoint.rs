@@ -1,29 +1,63 @@
-use super::get_response_image;
-use crate::common::ImageHandlerOptions;
+use super::{
+    get_response_batch, get_response_image, get_response_info, BatchJob, BatchOutput,
+    RequestOptions,
+};
+use crate::common::{ImageHandlerOptions, InputFormat};
 use crate::error::ResponseError;
-use crate::utils::get_image_from_base64;
-use image::ImageFormat;
+use crate::utils::{get_image_from_base64, get_image_from_url};
+use futures::FutureExt;
 use lambda_http::RequestExt;
 use lambda_http::{Body, Error, Request, Response};
 use multer::parse_boundary;
 use reqwest::{header, StatusCode};
 use serde::Deserialize;
+use serde_aux::prelude::*;
+use serde_json::{Map, Value};
 use std::convert::Infallible;
 
-#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+struct PostImageQuery {
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub info: bool,
+
+    /// How the results are returned when several files are uploaded.
+    pub output: Option<BatchOutput>,
+
+    #[serde(flatten)]
+    pub options: ImageHandlerOptions,
+}
+
+/// An image of a JSON body, from a URL or base64 encoded, with the options
+/// that replace the query parameters.
+#[derive(Debug, Deserialize)]
+struct JsonImage {
+    source_url: Option<String>,
+    base64_data: Option<String>,
+
+    /// The name of the outputs in a batch, the file name of the URL by default.
+    name: Option<String>,
+
+    #[serde(default)]
+    options: Map<String, Value>,
+}
+
 struct FormFile {
     file_name: String,
     bytes: Vec<u8>,
-    content_type: String,
+    content_type: Option<String>,
+}
+
+/// The files and the other fields of a `form-data` body.
+struct FormData {
+    files: Vec<FormFile>,
+    fields: Vec<(String, String)>,
 }
 
 pub async fn post_image_endpoint(request: Request) -> Result<Response<Body>, Error> {
     tracing::info!("url: {:?}", request.uri().path_and_query());
 
-    let query_map = request.query_string_parameters();
-    let query_str = query_map.to_query_string();
-    let query: ImageHandlerOptions = serde_qs::from_str(&query_str)
-        .map_err(|e| ResponseError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+    // The options in the body replace the ones in the query string
+    let mut params = RequestOptions::from_query(&request.query_string_parameters());
 
     let content_type = request
         .headers()
@@ -34,73 +68,222 @@ pub async fn post_image_endpoint(request: Request) -> Result<Response<Body>, Err
         .to_str()
         .map_err(ResponseError::from_error)?;
 
-    let mime: mime::Mime = content_type.parse().map_err(ResponseError::from_error)?;
+    let mime: mime::Mime = content_type.parse().map_err(|e| {
+        ResponseError::new(
+            StatusCode::BAD_REQUEST,
+            format!("invalid content-type `{content_type}`: {e}"),
+        )
+    })?;
     let bytes = request.body().to_vec();
 
-    let (buffer, format) = if mime == mime::APPLICATION_JSON {
-        get_body_base64_bytes(bytes).await?
-    } else {
-        get_form_file_bytes(bytes, content_type).await?
+    // The parameters like `charset=utf-8` are ignored, except the boundary of `form-data`
+    let (buffer, format) = match mime.essence_str() {
+        _ if mime.type_() == mime::IMAGE => get_raw_body_bytes(bytes, &mime)?,
+        "application/octet-stream" => get_raw_body_bytes(bytes, &mime)?,
+        "application/json" => {
+            let image = match get_json_body(bytes)? {
+                // An array describes several images processed as a batch
+                Value::Array(images) => {
+                    let query: PostImageQuery = params.parse()?;
+                    let jobs = images
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, image)| get_json_job(index, image, &params))
+                        .collect();
+
+                    let output = query.output.unwrap_or_default();
+                    return get_response_batch(jobs, output, query.info).await;
+                }
+                image => parse_json_image(image)?,
+            };
+
+            params.extend_json(image.options)?;
+            get_json_image_bytes(image.source_url, image.base64_data).await?
+        }
+        "multipart/form-data" => {
+            let FormData { mut files, fields } = get_form_data(bytes, content_type).await?;
+            for (name, value) in fields {
+                params.insert(name, value);
+            }
+
+            if files.len() > 1 {
+                let query: PostImageQuery = params.parse()?;
+                let jobs = files
+                    .into_iter()
+                    .map(|file| {
+                        let name = file.file_name.clone();
+                        let source = get_form_file_bytes(file)
+                            .map(|(buffer, format)| (buffer, format, query.options.clone()));
+                        BatchJob::ready(name, source)
+                    })
+                    .collect();
+
+                let output = query.output.unwrap_or_default();
+                return get_response_batch(jobs, output, query.info).await;
+            }
+
+            let file = files
+                .pop()
+                .ok_or_else(|| ResponseError::new(StatusCode::BAD_REQUEST, "no file"))?;
+
+            get_form_file_bytes(file)?
+        }
+        _ => {
+            return Err(ResponseError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!(
+                    "unsupported content-type `{}`, expected an image, JSON or form-data",
+                    mime.essence_str()
+                ),
+            )
+            .into())
+        }
     };
 
-    get_response_image(buffer, format, query.into()).await
+    let query: PostImageQuery = params.parse()?;
+
+    if query.info {
+        return get_response_info(buffer, format).await;
+    }
+
+    get_response_image(buffer, format, query.options).await
+}
+
+/// Returns a body that is the image itself, the format is detected from the content
+/// and the content type is only used if the format can't be detected.
+fn get_raw_body_bytes(body: Vec<u8>, mime: &mime::Mime) -> Result<(Vec<u8>, InputFormat), Error> {
+    if body.is_empty() {
+        return Err(ResponseError::new(StatusCode::BAD_REQUEST, "the body is empty").into());
+    }
+
+    let format = InputFormat::from_bytes(&body)
+        .or_else(|| InputFormat::from_mime_type(mime.essence_str()))
+        .ok_or_else(|| {
+            ResponseError::new(
+                StatusCode::BAD_REQUEST,
+                "unable to detect the image format of the body",
+            )
+        })?;
+
+    Ok((body, format))
+}
+
+fn get_json_body(body: Vec<u8>) -> Result<Value, Error> {
+    serde_json::from_slice(&body)
+        .map_err(|e| ResponseError::new(StatusCode::BAD_REQUEST, e.to_string()).into())
 }
 
-async fn get_body_base64_bytes(body: Vec<u8>) -> Result<(Vec<u8>, ImageFormat), Error> {
-    #[derive(Debug, Deserialize)]
-    struct Data {
-        base64_data: String,
+fn parse_json_image(image: Value) -> Result<JsonImage, Error> {
+    serde_json::from_value(image)
+        .map_err(|e| ResponseError::new(StatusCode::BAD_REQUEST, e.to_string()).into())
+}
+
+/// Returns the image of a JSON body, downloaded from `source_url` or decoded from `base64_data`.
+async fn get_json_image_bytes(
+    source_url: Option<String>,
+    base64_data: Option<String>,
+) -> Result<(Vec<u8>, InputFormat), Error> {
+    match (source_url, base64_data) {
+        (Some(url), None) => get_image_from_url(url).await,
+        (None, Some(base64_data)) => get_image_from_base64(base64_data).await,
+        (Some(_), Some(_)) => Err(ResponseError::new(
+            StatusCode::BAD_REQUEST,
+            "the body cannot contains both, `source_url` and `base64_data`",
+        )
+        .into()),
+        (None, None) => Err(ResponseError::new(
+            StatusCode::BAD_REQUEST,
+            "the body should contains `source_url` or `base64_data`",
+        )
+        .into()),
     }
+}
+
+/// A job for an image of a JSON array, its options are merged with the query parameters
+/// and an invalid image only fails its own job.
+fn get_json_job(index: usize, image: Value, params: &RequestOptions) -> BatchJob {
+    let image = match parse_json_image(image) {
+        Ok(image) => image,
+        Err(err) => return BatchJob::ready(format!("image-{}", index + 1), Err(err)),
+    };
+
+    let name = image
+        .name
+        .clone()
+        .or_else(|| image.source_url.as_deref().and_then(url_file_name))
+        .unwrap_or_else(|| format!("image-{}", index + 1));
 
-    let data = serde_json::from_slice::<Data>(&body)?;
-    get_image_from_base64(data.base64_data).await
+    let mut params = params.clone();
+    let source = async move {
+        params.extend_json(image.options)?;
+        let options: ImageHandlerOptions = params.parse()?;
+        let (buffer, format) = get_json_image_bytes(image.source_url, image.base64_data).await?;
+        Ok((buffer, format, options))
+    };
+
+    BatchJob {
+        name,
+        source: source.boxed(),
+    }
 }
 
-async fn get_form_file_bytes(
-    body: Vec<u8>,
-    content_type: &str,
-) -> Result<(Vec<u8>, ImageFormat), Error> {
-    let boundary = parse_boundary(content_type).map_err(ResponseError::from_error)?;
+/// The last segment of the path of a URL, like `photo.jpg` for `https://example.com/photo.jpg?v=2`.
+fn url_file_name(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let file_name = url.path_segments()?.next_back()?;
+    (!file_name.is_empty()).then(|| file_name.to_owned())
+}
+
+async fn get_form_data(body: Vec<u8>, content_type: &str) -> Result<FormData, Error> {
+    let boundary = parse_boundary(content_type).map_err(|e| {
+        ResponseError::new(
+            StatusCode::BAD_REQUEST,
+            format!("invalid multipart/form-data content-type: {e}"),
+        )
+    })?;
     let mut multipart = multer::Multipart::new(
         futures::stream::once(async move { Ok::<_, Infallible>(body) }),
         boundary,
     );
 
-    let mut form_file: Option<FormFile> = None;
+    let mut form_files = Vec::new();
+    let mut fields = Vec::new();
 
-    while let Some(field) = multipart.next_field().await.unwrap() {
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ResponseError::new(StatusCode::BAD_REQUEST, e.to_string()))?
+    {
         if let Some(file_name) = field.file_name() {
-            if form_file.is_some() {
-                return Err(ResponseError::new(
-                    StatusCode::BAD_REQUEST,
-                    "expected 1 file but received more than 1",
-                )
-                .into());
-            }
-
             let file_name = file_name.to_owned();
-            let mime_type = field.content_type().ok_or_else(|| {
-                ResponseError::new(StatusCode::BAD_REQUEST, "unable to get file content-type")
-            })?;
+            let content_type = field
+                .content_type()
+                .map(|mime_type| mime_type.essence_str().to_owned());
 
-            let content_type = mime_type.essence_str().to_owned();
             let bytes = field.bytes().await?.to_vec();
-            form_file = Some(FormFile {
+            form_files.push(FormFile {
                 file_name,
                 bytes,
                 content_type,
             });
+        } else if let Some(name) = field.name().map(str::to_owned) {
+            fields.push((name, field.text().await?));
         }
     }
 
-    match form_file {
-        Some(file) => {
-            let buffer = file.bytes;
-            let format = ImageFormat::from_mime_type(&file.content_type)
-                .ok_or_else(|| ResponseError::new(StatusCode::BAD_REQUEST, "expected image"))?;
+    Ok(FormData {
+        files: form_files,
+        fields,
+    })
+}
 
-            Ok((buffer, format))
-        }
-        None => Err(ResponseError::new(StatusCode::BAD_REQUEST, "no file").into()),
-    }
+fn get_form_file_bytes(file: FormFile) -> Result<(Vec<u8>, InputFormat), Error> {
+    let content_type = file.content_type.ok_or_else(|| {
+        ResponseError::new(StatusCode::BAD_REQUEST, "unable to get file content-type")
+    })?;
+
+    let format = InputFormat::from_mime_type(&content_type)
+        .ok_or_else(|| ResponseError::new(StatusCode::BAD_REQUEST, "expected image"))?;
+
+    Ok((file.bytes, format))
 }
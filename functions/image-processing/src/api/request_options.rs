@@ -0,0 +1,94 @@
+use crate::common::Violations;
+use crate::error::ResponseError;
+use lambda_http::aws_lambda_events::query_map::QueryMap;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// The options of a request collected from the query string and the body,
+/// a value from the body replaces the query parameter with the same name.
+#[derive(Debug, Default, Clone)]
+pub struct RequestOptions(BTreeMap<String, String>);
+
+impl RequestOptions {
+    pub fn from_query(query: &QueryMap) -> Self {
+        let params = query
+            .iter()
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+
+        Self(params)
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.0.insert(key.into(), value.into());
+    }
+
+    /// Adds the values of a JSON object like `{ "width": 200, "widths": [320, 640] }`,
+    /// arrays are joined with commas and `null` removes the option.
+    pub fn extend_json(&mut self, options: Map<String, Value>) -> Result<(), ResponseError> {
+        for (key, value) in options {
+            let value = match value {
+                Value::Null => {
+                    self.0.remove(&key);
+                    continue;
+                }
+                Value::Array(values) => values
+                    .into_iter()
+                    .map(|value| json_scalar(&key, value))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(","),
+                value => json_scalar(&key, value)?,
+            };
+
+            self.0.insert(key, value);
+        }
+
+        Ok(())
+    }
+
+    /// Parses the options as if all of them were in the query string,
+    /// reporting every invalid option at once.
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T, ResponseError> {
+        let err = match parse_query::<T>(&self.0)? {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+
+        // `serde_qs` stops at the first invalid option, so each one is parsed alone
+        // to find the others, the options are optional so they can be parsed separately
+        let mut violations = Violations::default();
+        for (key, value) in &self.0 {
+            let param = BTreeMap::from([(key.clone(), value.clone())]);
+            if let Err(e) = parse_query::<T>(&param)? {
+                violations.push(format!("invalid `{key}`: {e}"));
+            }
+        }
+
+        if violations.is_empty() {
+            violations.push(err.to_string());
+        }
+
+        Err(violations.into_error())
+    }
+}
+
+fn parse_query<T: DeserializeOwned>(
+    params: &BTreeMap<String, String>,
+) -> Result<Result<T, serde_qs::Error>, ResponseError> {
+    let query_str = serde_qs::to_string(params).map_err(ResponseError::from_error)?;
+    Ok(serde_qs::from_str(&query_str))
+}
+
+fn json_scalar(key: &str, value: Value) -> Result<String, ResponseError> {
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(ResponseError::new(
+            StatusCode::BAD_REQUEST,
+            format!("invalid option `{key}`, expected a string, number, boolean or array"),
+        )),
+    }
+}
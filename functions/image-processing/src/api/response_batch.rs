@@ -0,0 +1,319 @@
+use super::{process_outputs, NamedOutput, ZipResponse, MAX_RESPONSE_BYTES};
+use crate::common::{image_info, ImageHandlerOptions, InputFormat};
+use crate::error::ResponseError;
+use base64::Engine as _;
+use futures::{
+    future::{self, BoxFuture},
+    stream::{self, BoxStream},
+    Future, FutureExt, StreamExt,
+};
+use http::{header, StatusCode};
+use lambda_http::{Body, Response};
+use lambda_runtime::Error;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+use tokio::{runtime::Handle, task};
+
+/// The maximum number of images processed in a single request.
+pub const MAX_BATCH_IMAGES: usize = 20;
+
+/// The maximum number of images downloaded and processed at the same time.
+pub const MAX_CONCURRENT_JOBS: usize = 4;
+
+/// The name of the source image and its result.
+type JobResult<T> = (String, Result<T, Error>);
+
+/// How the results of several images are returned.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchOutput {
+    /// A ZIP archive with the outputs, and an `errors.json` file if any image failed
+    #[default]
+    Zip,
+    /// A `multipart/mixed` body with a part for each output or error
+    Multipart,
+    /// A JSON array with the base64 encoded outputs or the error of each image
+    Json,
+}
+
+/// The image of a batch and the options to process it with.
+pub type BatchSource = (Vec<u8>, InputFormat, ImageHandlerOptions);
+
+/// An image of a batch, `source` resolves the image and its options, like downloading it,
+/// and runs concurrently with the other images.
+pub struct BatchJob {
+    pub name: String,
+    pub source: BoxFuture<'static, Result<BatchSource, Error>>,
+}
+
+impl BatchJob {
+    /// A job for an image that is already read, or the error that prevented reading it.
+    pub fn ready(name: impl Into<String>, source: Result<BatchSource, Error>) -> Self {
+        Self {
+            name: name.into(),
+            source: future::ready(source).boxed(),
+        }
+    }
+}
+
+/// Processes each image with its options, the errors are reported for each image
+/// in the response instead of failing the whole batch.
+///
+/// With `info` the response is a JSON array with the info of each image.
+pub async fn get_response_batch(
+    jobs: Vec<BatchJob>,
+    output: BatchOutput,
+    info: bool,
+) -> Result<Response<Body>, Error> {
+    if jobs.len() > MAX_BATCH_IMAGES {
+        return Err(ResponseError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "expected at most {MAX_BATCH_IMAGES} images but received {}",
+                jobs.len()
+            ),
+        )
+        .into());
+    }
+
+    if info {
+        let mut results = run_jobs(jobs, |_, (buffer, format, _)| async move {
+            Ok(serde_json::to_value(image_info(&buffer, format)?)?)
+        });
+
+        let mut infos = Vec::new();
+        while let Some((name, result)) = results.next().await {
+            infos.push(match result {
+                Ok(info) => json!({ "file_name": name, "info": info }),
+                Err(err) => json!({ "file_name": name, "error": error_json(err) }),
+            });
+        }
+
+        return json_response(infos);
+    }
+
+    let results = run_jobs(jobs, |name, (buffer, format, options)| async move {
+        let mut outputs = Vec::new();
+        process_outputs(&output_name(&name), buffer, format, &options, |output| {
+            outputs.push(output);
+            Ok(())
+        })
+        .await?;
+
+        Ok(outputs)
+    });
+
+    match output {
+        BatchOutput::Zip => zip_response(results).await,
+        BatchOutput::Multipart => multipart_response(results).await,
+        BatchOutput::Json => json_outputs_response(results).await,
+    }
+}
+
+/// Runs up to `MAX_CONCURRENT_JOBS` jobs at the same time, the results are returned
+/// in the order of the jobs.
+///
+/// Only the sources are awaited in the request task, `run` decodes, processes and encodes
+/// the image so it's called in a blocking thread to not block the async runtime.
+fn run_jobs<T, F, Fut>(jobs: Vec<BatchJob>, run: F) -> BoxStream<'static, JobResult<T>>
+where
+    T: Send + 'static,
+    F: Fn(String, BatchSource) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    stream::iter(jobs)
+        .map(move |job| {
+            let run = run.clone();
+
+            async move {
+                let result = match job.source.await {
+                    Ok(source) => {
+                        let name = job.name.clone();
+                        task::spawn_blocking(move || Handle::current().block_on(run(name, source)))
+                            .await
+                            .unwrap_or_else(|e| Err(ResponseError::from_error(e).into()))
+                    }
+                    Err(err) => Err(err),
+                };
+
+                (job.name, result)
+            }
+        })
+        .buffered(MAX_CONCURRENT_JOBS)
+        .boxed()
+}
+
+async fn zip_response(
+    mut results: BoxStream<'static, JobResult<Vec<NamedOutput>>>,
+) -> Result<Response<Body>, Error> {
+    let mut zip = ZipResponse::new();
+    let mut errors = Vec::new();
+
+    while let Some((name, result)) = results.next().await {
+        match result {
+            Ok(outputs) => {
+                for output in outputs {
+                    zip.add_file(&output.file_name, &output.buf)?;
+                }
+            }
+            Err(err) => {
+                let mut error = error_json(err);
+                error["file_name"] = name.into();
+                errors.push(error);
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        zip.add_file("errors.json", &serde_json::to_vec_pretty(&errors)?)?;
+    }
+
+    zip.into_response("images.zip")
+}
+
+async fn multipart_response(
+    mut results: BoxStream<'static, JobResult<Vec<NamedOutput>>>,
+) -> Result<Response<Body>, Error> {
+    let boundary = multipart_boundary();
+    let mut body = Vec::new();
+
+    while let Some((name, result)) = results.next().await {
+        match result {
+            Ok(outputs) => {
+                for output in outputs {
+                    write_part(
+                        &mut body,
+                        &boundary,
+                        &output.content_type,
+                        &output.file_name,
+                        &output.buf,
+                    );
+                    check_response_size(body.len())?;
+                }
+            }
+            // The error parts are named after the source file
+            Err(err) => {
+                let mut error = error_json(err);
+                error["file_name"] = name.as_str().into();
+                let error = serde_json::to_vec(&error)?;
+                write_part(&mut body, &boundary, "application/json", &name, &error);
+                check_response_size(body.len())?;
+            }
+        }
+    }
+
+    body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+
+    Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/mixed; boundary={boundary}"),
+        )
+        .body(Body::Binary(body))
+        .map_err(Error::from)
+}
+
+async fn json_outputs_response(
+    mut results: BoxStream<'static, JobResult<Vec<NamedOutput>>>,
+) -> Result<Response<Body>, Error> {
+    let mut items = Vec::new();
+    // The base64 data is most of the body, the rest is checked once serialized
+    let mut data_len = 0;
+
+    while let Some((name, result)) = results.next().await {
+        items.push(match result {
+            Ok(outputs) => {
+                let mut json_outputs = Vec::with_capacity(outputs.len());
+                for output in outputs {
+                    let data = base64::engine::general_purpose::STANDARD.encode(&output.buf);
+                    data_len += data.len();
+                    check_response_size(data_len)?;
+
+                    json_outputs.push(json!({
+                        "file_name": output.file_name,
+                        "content_type": output.content_type,
+                        "data": data,
+                    }));
+                }
+
+                json!({ "file_name": name, "outputs": json_outputs })
+            }
+            Err(err) => json!({ "file_name": name, "error": error_json(err) }),
+        });
+    }
+
+    json_response(items)
+}
+
+/// Fails the whole batch once the response is larger than `MAX_RESPONSE_BYTES`,
+/// a response over the Lambda limit would be lost anyway.
+fn check_response_size(len: usize) -> Result<(), Error> {
+    if len as u64 <= MAX_RESPONSE_BYTES {
+        return Ok(());
+    }
+
+    Err(ResponseError::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        format!(
+            "the response is larger than {} MB, request fewer or smaller images",
+            MAX_RESPONSE_BYTES / 1024 / 1024
+        ),
+    )
+    .into())
+}
+
+fn json_response(results: Vec<Value>) -> Result<Response<Body>, Error> {
+    let body = serde_json::to_string(&results)?;
+    check_response_size(body.len())?;
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::Text(body))
+        .map_err(Error::from)
+}
+
+/// The error response of a single image, with its status code.
+fn error_json(err: Error) -> Value {
+    let err = ResponseError::from_boxed(err);
+    let mut json = err.to_json();
+    json["status"] = err.status().as_u16().into();
+    json
+}
+
+/// The name of the outputs of a file, without the directories, the extension
+/// and the characters that can't be used in a `Content-Disposition` header.
+fn output_name(file_name: &str) -> String {
+    let name = file_name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let stem = stem.replace(|c: char| c == '"' || c.is_control(), "_");
+
+    match stem.is_empty() {
+        true => "image".to_owned(),
+        false => stem,
+    }
+}
+
+/// A random boundary, the outputs are binary so it's unlikely to be found in them.
+fn multipart_boundary() -> String {
+    let random = RandomState::new().build_hasher().finish();
+    format!("image-batch-{random:016x}")
+}
+
+fn write_part(body: &mut Vec<u8>, boundary: &str, content_type: &str, file_name: &str, buf: &[u8]) {
+    let file_name = file_name.replace(|c: char| c == '"' || c.is_control(), "_");
+    let headers = format!(
+        "--{boundary}\r\n\
+         Content-Type: {content_type}\r\n\
+         Content-Disposition: attachment; filename=\"{file_name}\"\r\n\
+         Content-Length: {}\r\n\r\n",
+        buf.len()
+    );
+
+    body.extend_from_slice(headers.as_bytes());
+    body.extend_from_slice(buf);
+    body.extend_from_slice(b"\r\n");
+}
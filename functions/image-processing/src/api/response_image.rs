@@ -1,24 +1,122 @@
-use crate::common::{image_handler, ImageHandlerOptions};
+use super::ZipResponse;
+use crate::common::{
+    icon_handler, image_handler, image_mime_type, validate_outputs, ImageHandlerOptions,
+    InputFormat,
+};
 use http::{header, header::HeaderValue};
-use image::ImageFormat;
 use lambda_http::{Body, Response};
 use lambda_runtime::Error;
 
+/// Header with the quality used to encode the image when `max_bytes` is specified.
+pub const IMAGE_QUALITY_HEADER: &str = "x-image-quality";
+
+/// An encoded output and the file name it's returned with.
+pub struct NamedOutput {
+    pub file_name: String,
+    pub content_type: String,
+    pub buf: Vec<u8>,
+}
+
 pub async fn get_response_image(
     buffer: Vec<u8>,
-    format: ImageFormat,
+    format: InputFormat,
     options: ImageHandlerOptions,
 ) -> Result<Response<Body>, Error> {
+    // `icons` can't be combined with `widths` and `formats`, so they are checked first
+    validate_outputs(&options)?;
+
+    if let Some(icons) = options.icons {
+        let icons = icon_handler(buffer, format, icons, options).await?;
+        let content_disposition = format!("attachment; filename=\"{}\"", icons.file_name);
+
+        return Response::builder()
+            .header(header::CONTENT_TYPE, icons.content_type)
+            .header(header::CONTENT_DISPOSITION, content_disposition)
+            .body(Body::Binary(icons.buf))
+            .map_err(Error::from);
+    }
+
+    if options.widths.is_some() || options.formats.is_some() {
+        let mut zip = ZipResponse::new();
+        process_outputs("image", buffer, format, &options, |output| {
+            zip.add_file(&output.file_name, &output.buf)
+        })
+        .await?;
+
+        return zip.into_response("images.zip");
+    }
+
     let image_buffer = image_handler(buffer, format, options).await?;
-    let image_format: ImageFormat = image_buffer.format.into();
-    let res_content_type = format!("image/{}", image_format.extensions_str()[0]);
+    let mut builder =
+        Response::builder().header(header::CONTENT_TYPE, image_mime_type(image_buffer.format));
+
+    if let Some(quality) = image_buffer.quality {
+        builder = builder.header(IMAGE_QUALITY_HEADER, HeaderValue::from(quality as u16));
+    }
 
     let body = Body::Binary(image_buffer.buf);
-    Response::builder()
-        .header(
-            header::CONTENT_TYPE,
-            HeaderValue::from_str(&res_content_type).unwrap(),
-        )
-        .body(body)
-        .map_err(Error::from)
+    builder.body(body).map_err(Error::from)
+}
+
+/// Processes the image and passes each output to `on_output` as soon as it's encoded,
+/// one for each combination of `widths` and `formats` or the icons with `icons`.
+///
+/// The outputs are named after the source, like `image.png`, `image-320.webp` or `image-favicon.ico`.
+pub async fn process_outputs<F>(
+    name: &str,
+    buffer: Vec<u8>,
+    format: InputFormat,
+    options: &ImageHandlerOptions,
+    mut on_output: F,
+) -> Result<(), Error>
+where
+    F: FnMut(NamedOutput) -> Result<(), Error>,
+{
+    validate_outputs(options)?;
+
+    if let Some(icons) = options.icons {
+        let icons = icon_handler(buffer, format, icons, options.clone()).await?;
+        return on_output(NamedOutput {
+            file_name: format!("{name}-{}", icons.file_name),
+            content_type: icons.content_type.to_owned(),
+            buf: icons.buf,
+        });
+    }
+
+    let widths = match &options.widths {
+        Some(widths) => widths.0.iter().copied().map(Some).collect(),
+        None => vec![options.width],
+    };
+
+    let formats = match &options.formats {
+        Some(formats) => formats.0.iter().copied().map(Some).collect(),
+        None => vec![options.format],
+    };
+
+    for width in &widths {
+        for output_format in &formats {
+            let output_options = ImageHandlerOptions {
+                width: *width,
+                format: *output_format,
+                widths: None,
+                formats: None,
+                ..options.clone()
+            };
+
+            let image_buffer = image_handler(buffer.clone(), format, output_options).await?;
+            let extension = image_buffer.format.extensions_str()[0];
+            let file_name = match width {
+                Some(width) if options.widths.is_some() => format!("{name}-{width}.{extension}"),
+                _ => format!("{name}.{extension}"),
+            };
+
+            on_output(NamedOutput {
+                file_name,
+                content_type: image_mime_type(image_buffer.format).to_owned(),
+                buf: image_buffer.buf,
+            })?;
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,21 @@
+use crate::common::{image_info, InputFormat};
+use http::{header, header::HeaderValue};
+use lambda_http::{Body, Response};
+use lambda_runtime::Error;
+
+/// Returns the dimensions and metadata of the image as JSON, without processing it.
+pub async fn get_response_info(
+    buffer: Vec<u8>,
+    format: InputFormat,
+) -> Result<Response<Body>, Error> {
+    let info = image_info(&buffer, format)?;
+    let body = serde_json::to_string(&info)?;
+
+    Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        )
+        .body(Body::Text(body))
+        .map_err(Error::from)
+}
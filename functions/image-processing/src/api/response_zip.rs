@@ -0,0 +1,83 @@
+use crate::common::ZipWriter;
+use crate::error::ResponseError;
+use http::{header, StatusCode};
+use lambda_http::{Body, Response};
+use lambda_runtime::Error;
+use std::collections::HashSet;
+
+/// The maximum size of a ZIP or batch response, Lambda responses are limited to 6 MB
+/// and binary bodies are base64 encoded.
+pub const MAX_RESPONSE_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Builds an `application/zip` response in memory, each file is compressed and written
+/// to the archive as soon as it's added so only the archive is kept, up to `MAX_RESPONSE_BYTES`.
+pub struct ZipResponse {
+    zip: ZipWriter<Vec<u8>>,
+    names: HashSet<String>,
+}
+
+impl ZipResponse {
+    pub fn new() -> Self {
+        Self {
+            zip: ZipWriter::new(Vec::new()),
+            names: HashSet::new(),
+        }
+    }
+
+    /// Adds a file to the archive, a number is appended to the name if it's already in use.
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> Result<(), Error> {
+        let name = self.unique_name(name);
+        self.zip
+            .add_file(&name, data)
+            .map_err(ResponseError::from_error)?;
+
+        if self.zip.bytes_written() > MAX_RESPONSE_BYTES {
+            return Err(ResponseError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!(
+                    "the archive is larger than {} MB, request fewer or smaller images",
+                    MAX_RESPONSE_BYTES / 1024 / 1024
+                ),
+            )
+            .into());
+        }
+
+        self.names.insert(name);
+        Ok(())
+    }
+
+    pub fn into_response(self, file_name: &str) -> Result<Response<Body>, Error> {
+        let buf = self.zip.finish().map_err(ResponseError::from_error)?;
+
+        Response::builder()
+            .header(header::CONTENT_TYPE, "application/zip")
+            .header(
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            )
+            .body(Body::Binary(buf))
+            .map_err(Error::from)
+    }
+
+    fn unique_name(&self, name: &str) -> String {
+        if !self.names.contains(name) {
+            return name.to_owned();
+        }
+
+        let (stem, extension) = match name.rsplit_once('.') {
+            Some((stem, extension)) => (stem, format!(".{extension}")),
+            None => (name, String::new()),
+        };
+
+        (2..)
+            .map(|n| format!("{stem}-{n}{extension}"))
+            .find(|name| !self.names.contains(name))
+            .unwrap_or_else(|| name.to_owned())
+    }
+}
+
+impl Default for ZipResponse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,135 @@
+use crate::error::ResponseError;
+use http::StatusCode;
+use image::{
+    codecs::{
+        gif::{GifDecoder, GifEncoder, Repeat},
+        webp::WebPDecoder,
+    },
+    AnimationDecoder, Frame, Frames, ImageFormat,
+};
+use lambda_runtime::Error;
+use std::io::Cursor;
+
+/// The maximum number of frames of an animation.
+const MAX_ANIMATION_FRAMES: usize = 1000;
+
+/// The maximum number of pixels of all the frames together, each frame is decoded
+/// to the size of the whole animation with 4 bytes per pixel.
+const MAX_ANIMATION_PIXELS: u64 = 100_000_000;
+
+/// The frames of an animated image and how many times they should be repeated,
+/// `None` means the animation is played once.
+pub struct AnimatedImage {
+    pub frames: Vec<Frame>,
+    pub repeat: Option<Repeat>,
+}
+
+/// Decodes all the frames of an animated GIF or WebP,
+/// returns `None` if the format don't support animations or the image has a single frame.
+///
+/// An animation with more than `MAX_ANIMATION_FRAMES` frames or `MAX_ANIMATION_PIXELS` pixels
+/// is a `400`, the decoding stops as soon as a limit is exceeded.
+pub fn decode_animation(
+    buffer: &[u8],
+    format: ImageFormat,
+) -> Result<Option<AnimatedImage>, Error> {
+    let (frames, repeat) = match format {
+        ImageFormat::Gif => {
+            let decoder = GifDecoder::new(Cursor::new(buffer))?;
+            (collect_frames(decoder.into_frames())?, gif_repeat(buffer))
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(Cursor::new(buffer))?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+
+            (collect_frames(decoder.into_frames())?, webp_repeat(buffer))
+        }
+        _ => return Ok(None),
+    };
+
+    if frames.len() <= 1 {
+        return Ok(None);
+    }
+
+    Ok(Some(AnimatedImage { frames, repeat }))
+}
+
+fn collect_frames(frames: Frames) -> Result<Vec<Frame>, Error> {
+    let mut collected = Vec::new();
+    let mut pixels = 0;
+
+    // One more frame than allowed is read to know if the limit is exceeded
+    for frame in frames.take(MAX_ANIMATION_FRAMES + 1) {
+        let frame = frame?;
+        pixels += frame.buffer().width() as u64 * frame.buffer().height() as u64;
+
+        if collected.len() == MAX_ANIMATION_FRAMES {
+            return Err(too_large(format!(
+                "the animation has more than {MAX_ANIMATION_FRAMES} frames"
+            )));
+        }
+
+        if pixels > MAX_ANIMATION_PIXELS {
+            return Err(too_large(format!(
+                "the frames of the animation have more than {MAX_ANIMATION_PIXELS} pixels"
+            )));
+        }
+
+        collected.push(frame);
+    }
+
+    Ok(collected)
+}
+
+fn too_large(message: String) -> Error {
+    ResponseError::new(StatusCode::BAD_REQUEST, message).into()
+}
+
+/// Encodes the frames as an animated GIF.
+pub fn encode_gif(frames: Vec<Frame>, repeat: Option<Repeat>) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut buf, 10);
+        if let Some(repeat) = repeat {
+            encoder.set_repeat(repeat)?;
+        }
+
+        encoder.encode_frames(frames)?;
+    }
+
+    Ok(buf)
+}
+
+// The loop count is stored in the NETSCAPE2.0 application extension, GIFs without it play once
+fn gif_repeat(buffer: &[u8]) -> Option<Repeat> {
+    const NETSCAPE: &[u8] = b"NETSCAPE2.0";
+
+    let pos = buffer.windows(NETSCAPE.len()).position(|w| w == NETSCAPE)? + NETSCAPE.len();
+
+    // Sub-block: size (3), id (1), loop count (u16 little endian)
+    match buffer.get(pos..pos + 4)? {
+        [3, 1, 0, 0] => Some(Repeat::Infinite),
+        [3, 1, lo, hi] => Some(Repeat::Finite(u16::from_le_bytes([*lo, *hi]))),
+        _ => None,
+    }
+}
+
+// The loop count is stored in the ANIM chunk, unlike GIF it counts the first play
+fn webp_repeat(buffer: &[u8]) -> Option<Repeat> {
+    const ANIM: &[u8] = b"ANIM";
+
+    let pos = buffer.windows(ANIM.len()).position(|w| w == ANIM)? + ANIM.len();
+
+    // Chunk: size (4), background color (4), loop count (u16 little endian)
+    match buffer.get(pos + 8..pos + 10)? {
+        [0, 0] => Some(Repeat::Infinite),
+        [lo, hi] => match u16::from_le_bytes([*lo, *hi]) {
+            1 => None,
+            n => Some(Repeat::Finite(n - 1)),
+        },
+        _ => None,
+    }
+}
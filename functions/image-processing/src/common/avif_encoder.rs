@@ -0,0 +1,49 @@
+use super::ImageHandlerOptions;
+use crate::error::ResponseError;
+use image::DynamicImage;
+use lambda_runtime::Error;
+
+#[cfg(feature = "avif")]
+const DEFAULT_AVIF_QUALITY: u8 = 80;
+
+#[cfg(feature = "avif")]
+const DEFAULT_AVIF_SPEED: u8 = 6;
+
+/// Encodes the image as AVIF using the pure Rust `ravif` encoder.
+#[cfg(feature = "avif")]
+pub fn encode_avif(img: &DynamicImage, options: &ImageHandlerOptions) -> Result<Vec<u8>, Error> {
+    use ravif::{Encoder, Img, RGBA8};
+
+    let quality = options.quality.unwrap_or(DEFAULT_AVIF_QUALITY);
+    let speed = options.speed.unwrap_or(DEFAULT_AVIF_SPEED);
+
+    let rgba = img.to_rgba8();
+    let pixels = rgba
+        .pixels()
+        .map(|p| RGBA8::new(p[0], p[1], p[2], p[3]))
+        .collect::<Vec<_>>();
+
+    let encoded = Encoder::new()
+        .with_quality(quality as f32)
+        .with_alpha_quality(quality as f32)
+        .with_speed(speed)
+        .encode_rgba(Img::new(
+            pixels.as_slice(),
+            rgba.width() as usize,
+            rgba.height() as usize,
+        ))
+        .map_err(ResponseError::from_error)?;
+
+    Ok(encoded.avif_file)
+}
+
+#[cfg(not(feature = "avif"))]
+pub fn encode_avif(_img: &DynamicImage, _options: &ImageHandlerOptions) -> Result<Vec<u8>, Error> {
+    use reqwest::StatusCode;
+
+    Err(ResponseError::new(
+        StatusCode::BAD_REQUEST,
+        "avif output is not supported, the `avif` feature is not enabled",
+    )
+    .into())
+}
@@ -0,0 +1,32 @@
+use super::{Color, Gravity, Sides};
+use image::{imageops, DynamicImage, RgbaImage};
+
+/// Places the image in a canvas of the given size filled with `background`,
+/// the parts of the image outside of the canvas are cropped.
+pub fn extend_canvas(
+    img: &DynamicImage,
+    width: u32,
+    height: u32,
+    gravity: Gravity,
+    background: Color,
+) -> DynamicImage {
+    let mut canvas = RgbaImage::from_pixel(width, height, background.0);
+    let (x, y) = gravity.offset((width, height), (img.width(), img.height()));
+    imageops::overlay(&mut canvas, &img.to_rgba8(), x, y);
+    DynamicImage::ImageRgba8(canvas)
+}
+
+/// Adds the given space around the image filled with `color`.
+pub fn add_padding(img: &DynamicImage, sides: Sides, color: Color) -> DynamicImage {
+    let width = img.width().saturating_add(sides.horizontal());
+    let height = img.height().saturating_add(sides.vertical());
+
+    let mut canvas = RgbaImage::from_pixel(width, height, color.0);
+    imageops::overlay(
+        &mut canvas,
+        &img.to_rgba8(),
+        sides.left as i64,
+        sides.top as i64,
+    );
+    DynamicImage::ImageRgba8(canvas)
+}
@@ -0,0 +1,49 @@
+use image::DynamicImage;
+use qcms::{DataType, Intent, Profile, Transform};
+
+/// Converts the pixels of the image from the given ICC profile to sRGB.
+///
+/// The image is returned as is if the profile is already sRGB, isn't an RGB profile
+/// or can't be parsed. Images with more than 8 bits per channel are converted to 8 bits.
+pub fn convert_to_srgb(img: DynamicImage, icc: &[u8]) -> DynamicImage {
+    // Color space signature in the profile header
+    if icc.get(16..20) != Some(b"RGB ") {
+        return img;
+    }
+
+    let Some(input) = Profile::new_from_slice(icc, false) else {
+        tracing::warn!("ignoring invalid ICC profile");
+        return img;
+    };
+
+    if input.is_sRGB() {
+        return img;
+    }
+
+    let mut output = Profile::new_sRGB();
+    output.precache_output_transform();
+
+    let data_type = if img.color().has_alpha() {
+        DataType::RGBA8
+    } else {
+        DataType::RGB8
+    };
+
+    let Some(transform) = Transform::new(&input, &output, data_type, Intent::Perceptual) else {
+        tracing::warn!("unable to convert the ICC profile to sRGB");
+        return img;
+    };
+
+    match data_type {
+        DataType::RGBA8 => {
+            let mut rgba = img.into_rgba8();
+            transform.apply(&mut rgba);
+            DynamicImage::ImageRgba8(rgba)
+        }
+        _ => {
+            let mut rgb = img.into_rgb8();
+            transform.apply(&mut rgb);
+            DynamicImage::ImageRgb8(rgb)
+        }
+    }
+}
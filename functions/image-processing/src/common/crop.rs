@@ -0,0 +1,114 @@
+use super::{smart_crop_offset, AspectRatio, CropRect, Dimension, Gravity};
+use image::DynamicImage;
+use serde::Deserialize;
+
+/// The crop query parameters as received, a crop can be specified as:
+/// - `crop_x`, `crop_y`, `crop_width` and `crop_height`
+/// - `crop_width` and `crop_height` with an optional `gravity`
+/// - `crop_aspect` with an optional `gravity` or `crop_x` and `crop_y`
+///
+/// `gravity` alone doesn't crop, it's also used to position the image with `extent`,
+/// so with `extent` it can be combined with `crop_x` and `crop_y` and only positions the image.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct CropOptions {
+    pub crop_x: Option<Dimension>,
+    pub crop_y: Option<Dimension>,
+    pub crop_width: Option<Dimension>,
+    pub crop_height: Option<Dimension>,
+    pub crop_aspect: Option<AspectRatio>,
+    pub gravity: Option<Gravity>,
+}
+
+impl CropOptions {
+    pub fn is_empty(&self) -> bool {
+        self.crop_x.is_none()
+            && self.crop_y.is_none()
+            && self.crop_width.is_none()
+            && self.crop_height.is_none()
+            && self.crop_aspect.is_none()
+    }
+
+    /// Whether the crop area is positioned with `crop_x` or `crop_y` instead of `gravity`.
+    pub fn has_position(&self) -> bool {
+        self.crop_x.is_some() || self.crop_y.is_some()
+    }
+
+    /// Resolves the crop area for an image of the given size, returns `None` if there is no crop.
+    pub fn resolve(&self, width: u32, height: u32) -> Result<Option<CropRect>, String> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+
+        let crop_width = self.crop_width.map(|w| w.resolve(width));
+        let crop_height = self.crop_height.map(|h| h.resolve(height));
+
+        let (crop_width, crop_height) = match (self.crop_aspect, crop_width, crop_height) {
+            (Some(_), Some(_), Some(_)) => {
+                return Err(
+                    "`crop_aspect` cannot be used with both `crop_width` and `crop_height`"
+                        .to_owned(),
+                );
+            }
+            (Some(aspect), Some(w), None) => {
+                let h = (w as u64 * aspect.height as u64) / aspect.width as u64;
+                (w, h as u32)
+            }
+            (Some(aspect), None, Some(h)) => {
+                let w = (h as u64 * aspect.width as u64) / aspect.height as u64;
+                (w as u32, h)
+            }
+            (Some(aspect), None, None) => aspect.fit(width, height),
+            (None, Some(w), Some(h)) => (w, h),
+            (None, Some(_), None) => {
+                return Err("`crop_width` was specified without `crop_height`".to_owned());
+            }
+            (None, None, Some(_)) => {
+                return Err("`crop_height` was specified without `crop_width`".to_owned());
+            }
+            (None, None, None) => {
+                return Err(
+                    "crop requires `crop_width` and `crop_height` or `crop_aspect`".to_owned(),
+                );
+            }
+        };
+
+        let (crop_x, crop_y) = match (self.crop_x, self.crop_y, self.gravity) {
+            (Some(x), Some(y), _) => (x.resolve(width), y.resolve(height)),
+            (Some(_), None, _) => {
+                return Err("`crop_x` was specified without `crop_y`".to_owned());
+            }
+            (None, Some(_), _) => {
+                return Err("`crop_y` was specified without `crop_x`".to_owned());
+            }
+            (None, None, gravity) => {
+                let gravity = gravity.unwrap_or_default();
+                let (x, y) = gravity.offset((width, height), (crop_width, crop_height));
+
+                // A crop area bigger than the image starts at its edge, validation rejects it
+                (x.max(0) as u32, y.max(0) as u32)
+            }
+        };
+
+        Ok(Some(CropRect {
+            crop_x,
+            crop_y,
+            crop_width,
+            crop_height,
+        }))
+    }
+
+    /// Resolves the crop area for the given image, using the image content for `gravity=smart`.
+    pub fn resolve_for_image(&self, img: &DynamicImage) -> Result<Option<CropRect>, String> {
+        let crop = self.resolve(img.width(), img.height())?;
+
+        match crop {
+            Some(mut rect) if self.gravity == Some(Gravity::Smart) && !self.has_position() => {
+                let (x, y) = smart_crop_offset(img, rect.crop_width, rect.crop_height);
+                rect.crop_x = x;
+                rect.crop_y = y;
+                Ok(Some(rect))
+            }
+            _ => Ok(crop),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use super::{
+    encode_avif, encode_jpeg, encode_png, encode_webp, ImageByteBuffer, ImageHandlerOptions,
+    OutputFormat,
+};
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use lambda_runtime::Error;
+use std::io::Cursor;
+
+/// Returns the output format of the image, if not specified PNG is used when the result
+/// may have transparent pixels, PNG options are used or icons are generated, otherwise JPEG.
+pub fn output_format(options: &ImageHandlerOptions) -> OutputFormat {
+    if let Some(format) = options.format {
+        return format;
+    }
+
+    // The icons are always encoded as PNG
+    if options.icons.is_some() {
+        return OutputFormat::Png;
+    }
+
+    let needs_alpha = options.radius.is_some()
+        || options.mask.is_some()
+        || [options.background, options.border_color]
+            .iter()
+            .flatten()
+            .any(|color| !color.is_opaque());
+
+    let png_options = options.optimize || options.colors.is_some();
+
+    if needs_alpha || png_options {
+        OutputFormat::Png
+    } else {
+        OutputFormat::Jpeg
+    }
+}
+
+pub fn encode_image(
+    img: &DynamicImage,
+    options: &ImageHandlerOptions,
+) -> Result<ImageByteBuffer, Error> {
+    let (buf, format) = match output_format(options) {
+        OutputFormat::Jpeg => (encode_jpeg(img, options)?, ImageFormat::Jpeg),
+        OutputFormat::Png => (encode_png(img, options)?, ImageFormat::Png),
+        OutputFormat::Gif => (write_image(img, ImageOutputFormat::Gif)?, ImageFormat::Gif),
+        OutputFormat::WebP => (encode_webp(img, options)?, ImageFormat::WebP),
+        OutputFormat::Avif => (encode_avif(img, options)?, ImageFormat::Avif),
+    };
+
+    Ok(ImageByteBuffer {
+        buf,
+        format,
+        quality: None,
+    })
+}
+
+/// Encodes the image using the `image` crate encoders.
+pub fn write_image(img: &DynamicImage, format: ImageOutputFormat) -> Result<Vec<u8>, Error> {
+    let total_bytes: usize = (img.width() * img.height()).try_into().unwrap_or(0);
+    let mut cursor = Cursor::new(Vec::with_capacity(total_bytes));
+    img.write_to(&mut cursor, format)?;
+    Ok(cursor.into_inner())
+}
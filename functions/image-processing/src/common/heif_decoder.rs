@@ -0,0 +1,116 @@
+use super::Metadata;
+use crate::error::ResponseError;
+use image::DynamicImage;
+use lambda_runtime::Error;
+
+/// Decodes the primary image of a HEIF/HEIC file, returning its ICC profile, EXIF and XMP data.
+#[cfg(feature = "heif")]
+pub fn decode_heif(buffer: &[u8]) -> Result<(DynamicImage, Metadata), Error> {
+    use image::{RgbImage, RgbaImage};
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let context = HeifContext::read_from_bytes(buffer).map_err(ResponseError::from_error)?;
+    let handle = context
+        .primary_image_handle()
+        .map_err(ResponseError::from_error)?;
+
+    let has_alpha = handle.has_alpha_channel();
+    let chroma = if has_alpha {
+        RgbChroma::Rgba
+    } else {
+        RgbChroma::Rgb
+    };
+
+    let image = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(chroma), None)
+        .map_err(ResponseError::from_error)?;
+
+    let plane = image
+        .planes()
+        .interleaved
+        .ok_or_else(|| Error::from("failed to read the HEIF image"))?;
+
+    // Rows can be padded, so they are copied without the stride padding
+    let channels = if has_alpha { 4 } else { 3 };
+    let row_len = plane.width as usize * channels;
+    let pixels = plane
+        .data
+        .chunks(plane.stride)
+        .take(plane.height as usize)
+        .flat_map(|row| &row[..row_len])
+        .copied()
+        .collect();
+
+    let img = if has_alpha {
+        RgbaImage::from_raw(plane.width, plane.height, pixels).map(DynamicImage::ImageRgba8)
+    } else {
+        RgbImage::from_raw(plane.width, plane.height, pixels).map(DynamicImage::ImageRgb8)
+    };
+
+    let img = img.ok_or_else(|| Error::from("failed to read the HEIF image"))?;
+    Ok((img, heif_metadata(&handle)))
+}
+
+/// Returns the size and metadata of a HEIF/HEIC file without decoding the image.
+#[cfg(feature = "heif")]
+pub fn heif_info(buffer: &[u8]) -> Result<((u32, u32), Metadata), Error> {
+    use libheif_rs::HeifContext;
+
+    let context = HeifContext::read_from_bytes(buffer).map_err(ResponseError::from_error)?;
+    let handle = context
+        .primary_image_handle()
+        .map_err(ResponseError::from_error)?;
+
+    Ok(((handle.width(), handle.height()), heif_metadata(&handle)))
+}
+
+#[cfg(feature = "heif")]
+fn heif_metadata(handle: &libheif_rs::ImageHandle) -> Metadata {
+    let mut metadata = Metadata {
+        icc: handle.color_profile_raw().map(|profile| profile.data),
+        ..Metadata::default()
+    };
+
+    for block in handle.all_metadata() {
+        match &block.item_type.0 {
+            // Starts with the offset to the TIFF header
+            b"Exif" => {
+                let offset = block
+                    .raw_data
+                    .get(..4)
+                    .map(|o| u32::from_be_bytes([o[0], o[1], o[2], o[3]]) as usize);
+
+                metadata.exif = offset
+                    .and_then(|o| block.raw_data.get(4 + o..))
+                    .map(<[u8]>::to_vec);
+            }
+            b"mime" if block.content_type == "application/rdf+xml" => {
+                metadata.xmp = Some(block.raw_data);
+            }
+            _ => {}
+        }
+    }
+
+    metadata
+}
+
+#[cfg(not(feature = "heif"))]
+pub fn decode_heif(_buffer: &[u8]) -> Result<(DynamicImage, Metadata), Error> {
+    Err(heif_disabled())
+}
+
+#[cfg(not(feature = "heif"))]
+pub fn heif_info(_buffer: &[u8]) -> Result<((u32, u32), Metadata), Error> {
+    Err(heif_disabled())
+}
+
+#[cfg(not(feature = "heif"))]
+fn heif_disabled() -> Error {
+    use reqwest::StatusCode;
+
+    ResponseError::new(
+        StatusCode::BAD_REQUEST,
+        "heif input is not supported, the `heif` feature is not enabled",
+    )
+    .into()
+}
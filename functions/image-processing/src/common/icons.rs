@@ -0,0 +1,128 @@
+use super::{encode_png, extend_canvas, Color, Gravity, IconSet, ImageHandlerOptions, ZipWriter};
+use image::{imageops::FilterType, DynamicImage};
+use lambda_runtime::Error;
+use serde_json::json;
+
+/// The sizes of the favicon, 256 pixels is the largest size of an ICO entry.
+pub const FAVICON_SIZES: [u32; 5] = [16, 32, 48, 64, 256];
+
+/// The largest icon of the sets, vector images are rendered at this size.
+pub const MAX_ICON_SIZE: u32 = 512;
+
+/// The PNG icons of the app icon set for browsers, iOS and Android.
+const APP_ICONS: [(&str, u32); 5] = [
+    ("favicon-16x16.png", 16),
+    ("favicon-32x32.png", 32),
+    ("apple-touch-icon.png", 180),
+    ("android-chrome-192x192.png", 192),
+    ("android-chrome-512x512.png", 512),
+];
+
+/// The icons listed in the `manifest.json` snippet.
+const MANIFEST_ICONS: [&str; 2] = ["android-chrome-192x192.png", "android-chrome-512x512.png"];
+
+pub struct IconSetBuffer {
+    pub buf: Vec<u8>,
+    pub content_type: &'static str,
+    pub file_name: &'static str,
+}
+
+/// Generates the icons from the image, which is centered in a transparent square first.
+pub fn encode_icons(
+    img: &DynamicImage,
+    icons: IconSet,
+    options: &ImageHandlerOptions,
+) -> Result<IconSetBuffer, Error> {
+    let img = square(img);
+    let favicon = encode_ico(&img, &FAVICON_SIZES, options)?;
+
+    if icons == IconSet::Favicon {
+        return Ok(IconSetBuffer {
+            buf: favicon,
+            content_type: "image/x-icon",
+            file_name: "favicon.ico",
+        });
+    }
+
+    let mut zip = ZipWriter::new(Vec::new());
+    zip.add_file("favicon.ico", &favicon)?;
+
+    for (name, size) in APP_ICONS {
+        zip.add_file(name, &encode_png(&resize_icon(&img, size), options)?)?;
+    }
+
+    zip.add_file("manifest.json", manifest().as_bytes())?;
+
+    Ok(IconSetBuffer {
+        buf: zip.finish()?,
+        content_type: "application/zip",
+        file_name: "icons.zip",
+    })
+}
+
+/// Encodes an ICO file with a PNG compressed entry for each size.
+pub fn encode_ico(
+    img: &DynamicImage,
+    sizes: &[u32],
+    options: &ImageHandlerOptions,
+) -> Result<Vec<u8>, Error> {
+    let entries = sizes
+        .iter()
+        .map(|size| encode_png(&resize_icon(img, *size), options).map(|png| (*size, png)))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let directory_len = 6 + 16 * entries.len();
+    let data_len = entries.iter().map(|(_, png)| png.len()).sum::<usize>();
+
+    let mut ico = Vec::with_capacity(directory_len + data_len);
+    ico.extend_from_slice(&[0, 0, 1, 0]);
+    ico.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+
+    let mut offset = directory_len;
+    for (size, png) in &entries {
+        // A size of 0 means 256 pixels
+        let size = if *size >= 256 { 0 } else { *size as u8 };
+        ico.extend_from_slice(&[size, size, 0, 0]);
+        ico.extend_from_slice(&1u16.to_le_bytes());
+        ico.extend_from_slice(&32u16.to_le_bytes());
+        ico.extend_from_slice(&(png.len() as u32).to_le_bytes());
+        ico.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += png.len();
+    }
+
+    for (_, png) in &entries {
+        ico.extend_from_slice(png);
+    }
+
+    Ok(ico)
+}
+
+fn square(img: &DynamicImage) -> DynamicImage {
+    let size = img.width().max(img.height());
+    if img.width() == img.height() {
+        return img.clone();
+    }
+
+    extend_canvas(img, size, size, Gravity::Center, Color::TRANSPARENT)
+}
+
+fn resize_icon(img: &DynamicImage, size: u32) -> DynamicImage {
+    img.resize_exact(size, size, FilterType::Lanczos3)
+}
+
+fn manifest() -> String {
+    let icons = APP_ICONS
+        .iter()
+        .filter(|(name, _)| MANIFEST_ICONS.contains(name))
+        .map(|(name, size)| {
+            json!({
+                "src": format!("/{name}"),
+                "sizes": format!("{size}x{size}"),
+                "type": "image/png",
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let manifest = json!({ "icons": icons });
+    serde_json::to_string_pretty(&manifest).unwrap_or_default()
+}
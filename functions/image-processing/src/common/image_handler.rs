@@ -1,16 +1,21 @@
-use super::{CropRect, FlipImage};
+use super::{
+    add_padding, apply_mask, check_single_page, convert_to_srgb, decode_animation, decode_heif,
+    decode_jxl, embed_metadata, encode_animated_webp, encode_gif, encode_icons, encode_image,
+    encode_with_max_bytes, extend_canvas, extract_page, load_svg, read_metadata, render_svg,
+    round_corners, trim_bounds, validate_options, AnimatedImage, ChromaSubsampling, Color,
+    ColorProfile, CropOptions, CropRect, Dimension, FlipImage, IconSet, IconSetBuffer, InputFormat,
+    Mask, Metadata, MetadataMode, OutputFormat, Sides, Size, ValueList, DEFAULT_TRIM_THRESHOLD,
+    MAX_ICON_SIZE,
+};
 use crate::error::ResponseError;
-use image::{imageops::FilterType, ImageFormat, ImageOutputFormat};
+use image::{imageops::FilterType, DynamicImage, Frame, ImageFormat};
 use lambda_runtime::Error;
 use reqwest::StatusCode;
 use serde::Deserialize;
 use serde_aux::prelude::*;
 use std::io::Cursor;
 
-const DEFAULT_QUALITY: u8 = 100;
-const MAX_WITDH: u32 = 10_000;
-
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct ImageHandlerOptions {
     #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub width: Option<u32>,
@@ -44,23 +49,345 @@ pub struct ImageHandlerOptions {
     pub invert: bool,
 
     #[serde(flatten)]
-    pub crop: Option<CropRect>,
+    pub crop: CropOptions,
+
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub trim: bool,
+
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub trim_threshold: Option<u8>,
+
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub trim_padding: Option<u32>,
+
+    pub extent: Option<Size>,
+
+    pub pad: Option<Sides>,
+
+    pub background: Option<Color>,
+
+    pub border: Option<Sides>,
+
+    pub border_color: Option<Color>,
+
+    pub radius: Option<Dimension>,
+
+    pub mask: Option<Mask>,
+
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub frame: Option<usize>,
+
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub page: Option<usize>,
+
+    pub format: Option<OutputFormat>,
+
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub speed: Option<u8>,
+
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub lossless: bool,
+
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub near_lossless: Option<u8>,
+
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub alpha_quality: Option<u8>,
+
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub progressive: bool,
+
+    pub subsampling: Option<ChromaSubsampling>,
+
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub optimize: bool,
+
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub colors: Option<u16>,
+
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub dither: bool,
+
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_bytes: Option<usize>,
+
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub max_bytes_downscale: bool,
+
+    pub color_profile: Option<ColorProfile>,
+
+    pub metadata: Option<MetadataMode>,
+
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub keep_gps: bool,
+
+    pub icons: Option<IconSet>,
+
+    pub widths: Option<ValueList<u32>>,
+
+    pub formats: Option<ValueList<OutputFormat>>,
 }
 
 pub struct ImageByteBuffer {
     pub buf: Vec<u8>,
     pub format: ImageFormat,
+    /// The quality selected to fit in `max_bytes`.
+    pub quality: Option<u8>,
+}
+
+/// The regions that depend on the image content, resolved with the first frame
+/// and reused for the rest so all the frames of an animation have the same size.
+#[derive(Default)]
+struct ResolvedRegions {
+    trim: Option<Option<CropRect>>,
+    crop: Option<Option<CropRect>>,
 }
 
 #[tracing::instrument(skip(image_buffer), level = "INFO")]
 pub async fn image_handler(
     image_buffer: Vec<u8>,
-    image_format: ImageFormat,
+    input_format: InputFormat,
+    options: ImageHandlerOptions,
+) -> Result<ImageByteBuffer, Error> {
+    match input_format {
+        InputFormat::Svg => {
+            check_single_page(options.page)?;
+            return svg_handler(&image_buffer, options);
+        }
+        InputFormat::Image(format) => {
+            if let Some(animation) = decode_animation(&image_buffer, format)? {
+                check_single_page(options.page)?;
+                let metadata = read_metadata(&image_buffer, format);
+                return animation_handler(animation, metadata, options);
+            }
+        }
+        _ => {}
+    }
+
+    let (mut img, metadata) = decode_image(image_buffer, input_format, options.page)?;
+
+    if let Some(frame) = options.frame.filter(|frame| *frame > 0) {
+        return Err(ResponseError::new(
+            StatusCode::BAD_REQUEST,
+            format!("invalid `frame` {frame}, the image only has 1 frame"),
+        )
+        .into());
+    }
+
+    let (icc, metadata) = resolve_metadata(metadata, &options);
+    if let Some(icc) = icc {
+        img = convert_to_srgb(img, &icc);
+    }
+
+    let img = process_image(img, &options, &mut ResolvedRegions::default())?;
+    encode_output(&img, &options, &metadata)
+}
+
+/// Returns the height of the image resized to `width` keeping the aspect ratio, at least 1 pixel.
+pub fn resized_height(width: u32, img_width: u32, img_height: u32) -> u32 {
+    let height = (width as f64 / img_width.max(1) as f64) * img_height as f64;
+    (height as u32).max(1)
+}
+
+/// Applies `color_profile` and `metadata` to the metadata of the source image,
+/// returns the profile to convert the pixels to sRGB with and the metadata to keep.
+fn resolve_metadata(
+    mut metadata: Metadata,
+    options: &ImageHandlerOptions,
+) -> (Option<Vec<u8>>, Metadata) {
+    // Once converted to sRGB the source profile no longer describes the pixels
+    let icc = match options.color_profile.unwrap_or_default() {
+        ColorProfile::Srgb => metadata.icc.take(),
+        ColorProfile::Preserve => None,
+    };
+
+    let metadata = metadata.filter(options.metadata.unwrap_or_default(), options.keep_gps);
+    (icc, metadata)
+}
+
+/// Generates a favicon or an app icon set from the image, the options are applied before
+/// the image is centered in a square and resized to each icon size.
+///
+/// The icons are always converted to sRGB and have no metadata.
+#[tracing::instrument(skip(image_buffer), level = "INFO")]
+pub async fn icon_handler(
+    image_buffer: Vec<u8>,
+    input_format: InputFormat,
+    icons: IconSet,
+    options: ImageHandlerOptions,
+) -> Result<IconSetBuffer, Error> {
+    let img = match input_format {
+        // Rendered at the largest icon size instead of resizing the raster
+        InputFormat::Svg => {
+            check_single_page(options.page)?;
+            let tree = load_svg(&image_buffer)?;
+            let size = tree.size();
+            let scale = MAX_ICON_SIZE as f32 / size.width().max(size.height());
+            render_svg(&tree, Some((size.width() * scale).round() as u32))?
+        }
+        _ => {
+            let (img, metadata) = decode_image(image_buffer, input_format, options.page)?;
+            match metadata.icc {
+                Some(icc) => convert_to_srgb(img, &icc),
+                None => img,
+            }
+        }
+    };
+
+    let img = process_image(img, &options, &mut ResolvedRegions::default())?;
+    encode_icons(&img, icons, &options)
+}
+
+/// Decodes a still image or the selected page of a multi-page image,
+/// animated images are decoded as their first frame.
+fn decode_image(
+    image_buffer: Vec<u8>,
+    input_format: InputFormat,
+    page: Option<usize>,
+) -> Result<(DynamicImage, Metadata), Error> {
+    match input_format {
+        InputFormat::Image(format) => {
+            let page = extract_page(&image_buffer, format, page)?;
+            let metadata = read_metadata(&image_buffer, format);
+            let buffer = page.as_deref().unwrap_or(&image_buffer);
+            Ok((image::load(Cursor::new(buffer), format)?, metadata))
+        }
+        InputFormat::Svg => {
+            check_single_page(page)?;
+            let img = render_svg(&load_svg(&image_buffer)?, None)?;
+            Ok((img, Metadata::default()))
+        }
+        InputFormat::Heif => {
+            check_single_page(page)?;
+            decode_heif(&image_buffer)
+        }
+        InputFormat::Jxl => {
+            check_single_page(page)?;
+            Ok((decode_jxl(&image_buffer)?, Metadata::default()))
+        }
+    }
+}
+
+/// SVG images have no color profile or metadata, so `color_profile` and `metadata` have no effect.
+fn svg_handler(buffer: &[u8], options: ImageHandlerOptions) -> Result<ImageByteBuffer, Error> {
+    let tree = load_svg(buffer)?;
+
+    // SVGs are usually transparent, so they are returned as PNG by default
+    let options = ImageHandlerOptions {
+        format: options.format.or(Some(OutputFormat::Png)),
+        ..options
+    };
+
+    let size = tree.size().to_int_size();
+    validate_options(&options, size.width(), size.height())?;
+
+    // Rendered at the requested width instead of resizing the raster
+    let img = render_svg(&tree, options.width)?;
+    let options = ImageHandlerOptions {
+        width: None,
+        ..options
+    };
+
+    let img = process_image(img, &options, &mut ResolvedRegions::default())?;
+    encode_output(&img, &options, &Metadata::default())
+}
+
+/// The frames are converted to sRGB like still images, the metadata is only kept
+/// in WebP output as GIF can't store it.
+fn animation_handler(
+    animation: AnimatedImage,
+    metadata: Metadata,
     options: ImageHandlerOptions,
 ) -> Result<ImageByteBuffer, Error> {
+    let AnimatedImage { frames, repeat } = animation;
+    let (icc, metadata) = resolve_metadata(metadata, &options);
+    let to_srgb = |img: DynamicImage| match &icc {
+        Some(icc) => convert_to_srgb(img, icc),
+        None => img,
+    };
+
+    // Only GIF and WebP output are animated, other formats use the first frame
+    let frame = match options.format {
+        Some(OutputFormat::Gif | OutputFormat::WebP) | None => options.frame,
+        Some(_) => Some(options.frame.unwrap_or(0)),
+    };
+
+    if let Some(index) = frame {
+        let frame_count = frames.len();
+        let frame = frames.into_iter().nth(index).ok_or_else(|| {
+            ResponseError::new(
+                StatusCode::BAD_REQUEST,
+                format!("invalid `frame` {index}, the image has {frame_count} frames"),
+            )
+        })?;
+
+        let img = to_srgb(DynamicImage::ImageRgba8(frame.into_buffer()));
+        let img = process_image(img, &options, &mut ResolvedRegions::default())?;
+        return encode_output(&img, &options, &metadata);
+    }
+
+    if options.max_bytes.is_some() {
+        return Err(ResponseError::new(
+            StatusCode::BAD_REQUEST,
+            "`max_bytes` is not supported for animated images, use `frame` to select a frame",
+        )
+        .into());
+    }
+
+    let mut regions = ResolvedRegions::default();
+    let frames = frames
+        .into_iter()
+        .map(|frame| {
+            let delay = frame.delay();
+            let img = to_srgb(DynamicImage::ImageRgba8(frame.into_buffer()));
+            let img = process_image(img, &options, &mut regions)?;
+            Ok(Frame::from_parts(img.into_rgba8(), 0, 0, delay))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    if options.format == Some(OutputFormat::WebP) {
+        let buf = encode_animated_webp(frames, repeat, &options)?;
+        return Ok(ImageByteBuffer {
+            buf: embed_metadata(buf, ImageFormat::WebP, &metadata)?,
+            format: ImageFormat::WebP,
+            quality: None,
+        });
+    }
+
+    Ok(ImageByteBuffer {
+        buf: encode_gif(frames, repeat)?,
+        format: ImageFormat::Gif,
+        quality: None,
+    })
+}
+
+fn process_image(
+    mut img: DynamicImage,
+    options: &ImageHandlerOptions,
+    regions: &mut ResolvedRegions,
+) -> Result<DynamicImage, Error> {
+    // Trimming runs first, so the other options are relative to the trimmed image
+    let trim_rect = match regions.trim {
+        Some(rect) => rect,
+        None if options.trim => {
+            let threshold = options.trim_threshold.unwrap_or(DEFAULT_TRIM_THRESHOLD);
+            let padding = options.trim_padding.unwrap_or(0);
+            *regions.trim.insert(trim_bounds(&img, threshold, padding))
+        }
+        None => *regions.trim.insert(None),
+    };
+
+    if let Some(rect) = trim_rect {
+        img = img.crop_imm(rect.crop_x, rect.crop_y, rect.crop_width, rect.crop_height);
+    }
+
+    // The options only need to be validated once
+    if regions.crop.is_none() {
+        validate_options(options, img.width(), img.height())?;
+    }
+
     let ImageHandlerOptions {
         width,
-        quality,
         grayscale,
         blur,
         flip,
@@ -70,22 +397,19 @@ pub async fn image_handler(
         sharp,
         hue,
         invert,
-        crop,
-    } = options;
-
-    let mut img = image::load(Cursor::new(image_buffer), image_format)?;
+        ref crop,
+        extent,
+        pad,
+        background,
+        border,
+        border_color,
+        radius,
+        mask,
+        ..
+    } = *options;
 
     if let Some(width) = width {
-        if width > MAX_WITDH {
-            return Err(ResponseError::new(
-                StatusCode::BAD_REQUEST,
-                format!("invalid width, max width is {MAX_WITDH}"),
-            )
-            .into());
-        }
-
-        let height_f = (width as f64 / img.width() as f64) * img.height() as f64;
-        let height = height_f as u32;
+        let height = resized_height(width, img.width(), img.height());
         img = img.resize_exact(width, height, FilterType::Lanczos3);
     }
 
@@ -131,20 +455,58 @@ pub async fn image_handler(
         img.invert();
     }
 
-    if let Some(crop) = crop {
+    let crop_rect = match regions.crop {
+        Some(rect) => rect,
+        None => {
+            let rect = crop
+                .resolve_for_image(&img)
+                .map_err(|e| ResponseError::new(StatusCode::BAD_REQUEST, e))?;
+
+            *regions.crop.insert(rect)
+        }
+    };
+
+    if let Some(crop) = crop_rect {
         img = img.crop_imm(crop.crop_x, crop.crop_y, crop.crop_width, crop.crop_height);
     }
 
-    let total_bytes: usize = (img.width() * img.height()).try_into().unwrap_or(0);
-    let mut cursor = Cursor::new(Vec::with_capacity(total_bytes));
+    let background = background.unwrap_or(Color::WHITE);
 
-    img.write_to(
-        &mut cursor,
-        ImageOutputFormat::Jpeg(quality.unwrap_or(DEFAULT_QUALITY)),
-    )?;
+    if let Some(extent) = extent {
+        let gravity = crop.gravity.unwrap_or_default();
+        img = extend_canvas(&img, extent.width, extent.height, gravity, background);
+    }
 
-    Ok(ImageByteBuffer {
-        buf: cursor.into_inner(),
-        format: ImageFormat::Jpeg,
-    })
+    if let Some(pad) = pad {
+        img = add_padding(&img, pad, background);
+    }
+
+    if let Some(border) = border {
+        img = add_padding(&img, border, border_color.unwrap_or(Color::BLACK));
+    }
+
+    if let Some(radius) = radius {
+        let radius = radius.resolve(img.width().min(img.height()));
+        img = round_corners(&img, radius);
+    }
+
+    if let Some(mask) = mask {
+        img = apply_mask(&img, mask);
+    }
+
+    Ok(img)
+}
+
+fn encode_output(
+    img: &DynamicImage,
+    options: &ImageHandlerOptions,
+    metadata: &Metadata,
+) -> Result<ImageByteBuffer, Error> {
+    if let Some(max_bytes) = options.max_bytes {
+        return encode_with_max_bytes(img, options, max_bytes, metadata);
+    }
+
+    let mut encoded = encode_image(img, options)?;
+    encoded.buf = embed_metadata(encoded.buf, encoded.format, metadata)?;
+    Ok(encoded)
 }
@@ -0,0 +1,482 @@
+use super::{
+    heif_info, jxl_size, load_svg, read_metadata, read_pages, InputFormat, Metadata, PageInfo,
+};
+use crate::error::ResponseError;
+use exif::{DateTime, Exif, Field, In, Reader, Tag, Value};
+use lambda_runtime::Error;
+use reqwest::StatusCode;
+use roxmltree::{Document, Node};
+use serde::Serialize;
+use std::{collections::BTreeMap, io::Cursor};
+
+const DC_NS: &str = "http://purl.org/dc/elements/1.1/";
+const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const XMP_NS: &str = "http://ns.adobe.com/xap/1.0/";
+const EXIF_NS: &str = "http://ns.adobe.com/exif/1.0/";
+const EXIF_EX_NS: &str = "http://cipa.jp/exif/1.0/";
+const TIFF_NS: &str = "http://ns.adobe.com/tiff/1.0/";
+const AUX_NS: &str = "http://ns.adobe.com/exif/1.0/aux/";
+const PHOTOSHOP_NS: &str = "http://ns.adobe.com/photoshop/1.0/";
+
+/// Undefined EXIF values longer than this, like maker notes, are left out of the info.
+const MAX_UNDEFINED_LEN: usize = 64;
+
+/// The dimensions and the normalized metadata of an image.
+#[derive(Debug, Serialize)]
+pub struct ImageInfo {
+    pub format: String,
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub has_icc_profile: bool,
+    /// The pages of a multi-page TIFF or the entries of an ICO file, in the order used by `page`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<Vec<PageInfo>>,
+    #[serde(flatten)]
+    pub metadata: MetadataInfo,
+}
+
+/// The metadata merged from EXIF, XMP and IPTC, in that order of priority.
+#[derive(Debug, Default, Serialize)]
+pub struct MetadataInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera_make: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lens_model: Option<String>,
+    /// ISO 8601 date, with the time zone offset if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gps: Option<GpsInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copyright: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    /// All the EXIF fields of the main image, by tag name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub exif: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct GpsInfo {
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f64>,
+}
+
+/// Reads the dimensions and metadata of the image without decoding the pixels.
+pub fn image_info(buffer: &[u8], input_format: InputFormat) -> Result<ImageInfo, Error> {
+    let pages = match input_format {
+        InputFormat::Image(format) => read_pages(buffer, format),
+        _ => None,
+    };
+
+    let ((width, height), metadata) = match input_format {
+        InputFormat::Image(format) => {
+            let size = image::io::Reader::with_format(Cursor::new(buffer), format)
+                .into_dimensions()
+                .map_err(|e| ResponseError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+            (size, read_metadata(buffer, format))
+        }
+        InputFormat::Svg => {
+            let size = load_svg(buffer)?.size().to_int_size();
+            ((size.width(), size.height()), Metadata::default())
+        }
+        InputFormat::Heif => heif_info(buffer)?,
+        InputFormat::Jxl => (jxl_size(buffer)?, Metadata::default()),
+    };
+
+    Ok(ImageInfo {
+        format: input_format.extension().to_owned(),
+        mime_type: input_format.mime_type().to_owned(),
+        width,
+        height,
+        has_icc_profile: metadata.icc.is_some(),
+        pages,
+        metadata: MetadataInfo::from_metadata(&metadata),
+    })
+}
+
+impl MetadataInfo {
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let exif = metadata.exif.as_ref().and_then(|exif| {
+            Reader::new()
+                .read_raw(exif.clone())
+                .map_err(|e| tracing::warn!("ignoring unreadable EXIF data: {e}"))
+                .ok()
+        });
+
+        let xmp = metadata.xmp.as_deref().and_then(|xmp| {
+            let xmp = String::from_utf8_lossy(xmp);
+            XmpInfo::parse(xmp.trim_end_matches('\0'))
+        });
+
+        let iptc = metadata.iptc.as_deref().map(IptcInfo::parse);
+
+        let mut info = exif.as_ref().map(from_exif).unwrap_or_default();
+
+        if let Some(xmp) = xmp {
+            info.merge(xmp.0);
+        }
+
+        if let Some(iptc) = iptc {
+            info.merge(iptc.0);
+        }
+
+        info
+    }
+
+    // Fills the missing fields and adds the new keywords
+    fn merge(&mut self, other: MetadataInfo) {
+        let MetadataInfo {
+            camera_make,
+            camera_model,
+            lens_model,
+            capture_date,
+            gps,
+            orientation,
+            title,
+            description,
+            artist,
+            copyright,
+            keywords,
+            exif: _,
+        } = other;
+
+        self.camera_make = self.camera_make.take().or(camera_make);
+        self.camera_model = self.camera_model.take().or(camera_model);
+        self.lens_model = self.lens_model.take().or(lens_model);
+        self.capture_date = self.capture_date.take().or(capture_date);
+        self.gps = self.gps.or(gps);
+        self.orientation = self.orientation.or(orientation);
+        self.title = self.title.take().or(title);
+        self.description = self.description.take().or(description);
+        self.artist = self.artist.take().or(artist);
+        self.copyright = self.copyright.take().or(copyright);
+
+        for keyword in keywords {
+            if !self.keywords.contains(&keyword) {
+                self.keywords.push(keyword);
+            }
+        }
+    }
+}
+
+fn from_exif(exif: &Exif) -> MetadataInfo {
+    let text = |tag: Tag| exif.get_field(tag, In::PRIMARY).and_then(ascii_value);
+
+    let capture_date = [
+        (Tag::DateTimeOriginal, Tag::OffsetTimeOriginal),
+        (Tag::DateTimeDigitized, Tag::OffsetTimeDigitized),
+        (Tag::DateTime, Tag::OffsetTime),
+    ]
+    .into_iter()
+    .find_map(|(date_tag, offset_tag)| {
+        let Value::Ascii(ref date) = exif.get_field(date_tag, In::PRIMARY)?.value else {
+            return None;
+        };
+
+        let mut date = DateTime::from_ascii(date.first()?).ok()?;
+        if let Some(Value::Ascii(offset)) = exif
+            .get_field(offset_tag, In::PRIMARY)
+            .map(|field| &field.value)
+        {
+            if let Some(offset) = offset.first() {
+                date.parse_offset(offset).ok();
+            }
+        }
+
+        Some(format_date(&date))
+    });
+
+    let exif_fields = exif
+        .fields()
+        .filter(|field| field.ifd_num == In::PRIMARY)
+        .filter(|field| !matches!(&field.value, Value::Undefined(data, _) if data.len() > MAX_UNDEFINED_LEN))
+        .map(|field| {
+            let value = ascii_value(field)
+                .unwrap_or_else(|| field.display_value().with_unit(exif).to_string());
+            (field.tag.to_string(), value)
+        })
+        .collect();
+
+    MetadataInfo {
+        camera_make: text(Tag::Make),
+        camera_model: text(Tag::Model),
+        lens_model: text(Tag::LensModel),
+        capture_date,
+        gps: exif_gps(exif),
+        orientation: exif
+            .get_field(Tag::Orientation, In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0)),
+        title: None,
+        description: text(Tag::ImageDescription),
+        artist: text(Tag::Artist),
+        copyright: text(Tag::Copyright),
+        keywords: Vec::new(),
+        exif: exif_fields,
+    }
+}
+
+fn ascii_value(field: &Field) -> Option<String> {
+    let Value::Ascii(ref values) = field.value else {
+        return None;
+    };
+
+    let text = values
+        .iter()
+        .map(|value| String::from_utf8_lossy(value).trim().to_owned())
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    Some(text).filter(|text| !text.is_empty())
+}
+
+fn exif_gps(exif: &Exif) -> Option<GpsInfo> {
+    // Degrees, minutes and seconds, negative to the south and west
+    let coordinate = |tag: Tag, ref_tag: Tag, negative: u8| {
+        let Value::Rational(ref dms) = exif.get_field(tag, In::PRIMARY)?.value else {
+            return None;
+        };
+
+        let degrees = dms
+            .iter()
+            .zip([1.0, 60.0, 3600.0])
+            .map(|(value, scale)| value.to_f64() / scale)
+            .sum::<f64>();
+
+        let is_negative = match exif.get_field(ref_tag, In::PRIMARY)?.value {
+            Value::Ascii(ref values) => values.first()?.first() == Some(&negative),
+            _ => false,
+        };
+
+        Some(if is_negative { -degrees } else { degrees }).filter(|d| d.is_finite())
+    };
+
+    let latitude = coordinate(Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S')?;
+    let longitude = coordinate(Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W')?;
+
+    // The reference is 1 below sea level
+    let altitude = match exif.get_field(Tag::GPSAltitude, In::PRIMARY) {
+        Some(Field {
+            value: Value::Rational(altitude),
+            ..
+        }) => altitude.first().map(|altitude| {
+            let below = exif
+                .get_field(Tag::GPSAltitudeRef, In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+                == Some(1);
+            if below {
+                -altitude.to_f64()
+            } else {
+                altitude.to_f64()
+            }
+        }),
+        _ => None,
+    };
+
+    Some(GpsInfo {
+        latitude,
+        longitude,
+        altitude: altitude.filter(|a| a.is_finite()),
+    })
+}
+
+fn format_date(date: &DateTime) -> String {
+    let mut text = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        date.year, date.month, date.day, date.hour, date.minute, date.second
+    );
+
+    if let Some(offset) = date.offset {
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset = offset.unsigned_abs();
+        text.push_str(&format!("{sign}{:02}:{:02}", offset / 60, offset % 60));
+    }
+
+    text
+}
+
+struct XmpInfo(MetadataInfo);
+
+impl XmpInfo {
+    fn parse(xmp: &str) -> Option<Self> {
+        let doc = Document::parse(xmp)
+            .map_err(|e| tracing::warn!("ignoring invalid XMP packet: {e}"))
+            .ok()?;
+
+        let root = doc.root();
+
+        // Simple properties can be attributes of `rdf:Description` or child elements
+        let text = |ns: &str, name: &str| {
+            root.descendants().find_map(|node| {
+                let value = node
+                    .attribute((ns, name))
+                    .map(str::to_owned)
+                    .or_else(|| xmp_element(node, ns, name).and_then(xmp_text));
+
+                value.map(|v| v.trim().to_owned()).filter(|v| !v.is_empty())
+            })
+        };
+
+        let list = |ns: &str, name: &str| {
+            root.descendants()
+                .filter_map(|node| xmp_element(node, ns, name))
+                .flat_map(xmp_list)
+                .collect::<Vec<_>>()
+        };
+
+        let gps = text(EXIF_NS, "GPSLatitude")
+            .zip(text(EXIF_NS, "GPSLongitude"))
+            .and_then(|(lat, lon)| {
+                Some(GpsInfo {
+                    latitude: xmp_coordinate(&lat)?,
+                    longitude: xmp_coordinate(&lon)?,
+                    altitude: None,
+                })
+            });
+
+        let info = MetadataInfo {
+            camera_make: text(TIFF_NS, "Make"),
+            camera_model: text(TIFF_NS, "Model"),
+            lens_model: text(EXIF_EX_NS, "LensModel").or_else(|| text(AUX_NS, "Lens")),
+            capture_date: text(EXIF_NS, "DateTimeOriginal")
+                .or_else(|| text(PHOTOSHOP_NS, "DateCreated"))
+                .or_else(|| text(XMP_NS, "CreateDate")),
+            gps,
+            orientation: text(TIFF_NS, "Orientation").and_then(|o| o.parse().ok()),
+            title: list(DC_NS, "title").into_iter().next(),
+            description: list(DC_NS, "description").into_iter().next(),
+            artist: Some(list(DC_NS, "creator").join(", ")).filter(|a| !a.is_empty()),
+            copyright: list(DC_NS, "rights").into_iter().next(),
+            keywords: list(DC_NS, "subject"),
+            exif: BTreeMap::new(),
+        };
+
+        Some(Self(info))
+    }
+}
+
+fn xmp_element<'a, 'input>(
+    node: Node<'a, 'input>,
+    ns: &str,
+    name: &str,
+) -> Option<Node<'a, 'input>> {
+    let tag = node.tag_name();
+    (node.is_element() && tag.namespace() == Some(ns) && tag.name() == name).then_some(node)
+}
+
+fn xmp_text(node: Node) -> Option<String> {
+    node.text().map(str::to_owned)
+}
+
+// The items of a `rdf:Bag`, `rdf:Seq` or `rdf:Alt`, or the text of a simple property
+fn xmp_list(node: Node) -> Vec<String> {
+    let items: Vec<_> = node
+        .descendants()
+        .filter(|n| n.is_element() && n.tag_name().namespace() == Some(RDF_NS))
+        .filter(|n| n.tag_name().name() == "li")
+        .filter_map(|n| n.text())
+        .map(|text| text.trim().to_owned())
+        .filter(|text| !text.is_empty())
+        .collect();
+
+    if !items.is_empty() {
+        return items;
+    }
+
+    xmp_text(node)
+        .map(|text| text.trim().to_owned())
+        .filter(|text| !text.is_empty())
+        .into_iter()
+        .collect()
+}
+
+// XMP coordinates are `DDD,MM,SSk` or `DDD,MM.mmk`, where `k` is N, S, E or W
+fn xmp_coordinate(value: &str) -> Option<f64> {
+    let direction = value.chars().last()?;
+    let parts = value[..value.len() - direction.len_utf8()].split(',');
+
+    let degrees = parts
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(part, scale)| part.trim().parse::<f64>().map(|v| v / scale))
+        .sum::<Result<f64, _>>()
+        .ok()?;
+
+    match direction {
+        'N' | 'E' => Some(degrees),
+        'S' | 'W' => Some(-degrees),
+        _ => None,
+    }
+}
+
+struct IptcInfo(MetadataInfo);
+
+impl IptcInfo {
+    // Records: tag marker (0x1C), record number, dataset number, size (u16), data
+    fn parse(mut data: &[u8]) -> Self {
+        let mut info = MetadataInfo::default();
+        let mut date = None;
+        let mut time = None;
+
+        while let [0x1C, record, dataset, hi, lo, rest @ ..] = data {
+            // Sizes with the high bit set use the extended format, not used for text
+            let size = u16::from_be_bytes([*hi, *lo]) as usize;
+            if size & 0x8000 != 0 || rest.len() < size {
+                break;
+            }
+
+            let value = String::from_utf8_lossy(&rest[..size]).trim().to_owned();
+            data = &rest[size..];
+
+            if *record != 2 || value.is_empty() {
+                continue;
+            }
+
+            match dataset {
+                5 => info.title = Some(value),
+                25 => info.keywords.push(value),
+                55 => date = Some(value),
+                60 => time = Some(value),
+                80 => info.artist = Some(value),
+                116 => info.copyright = Some(value),
+                120 => info.description = Some(value),
+                _ => {}
+            }
+        }
+
+        info.capture_date = date.and_then(|date| iptc_date(&date, time.as_deref()));
+        Self(info)
+    }
+}
+
+// Date as `CCYYMMDD` and time as `HHMMSS±HHMM`
+fn iptc_date(date: &str, time: Option<&str>) -> Option<String> {
+    if date.len() != 8 || !date.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let mut text = format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]);
+
+    if let Some(time) = time.filter(|t| t.len() >= 6 && t.is_ascii()) {
+        text.push_str(&format!("T{}:{}:{}", &time[..2], &time[2..4], &time[4..6]));
+        if time.len() == 11 {
+            text.push_str(&format!("{}:{}", &time[6..9], &time[9..]));
+        }
+    }
+
+    Some(text)
+}
@@ -0,0 +1,145 @@
+use image::ImageFormat;
+
+/// The bytes searched for the `<svg` element, after the XML declaration and comments.
+const SVG_SNIFF_LEN: usize = 4096;
+
+/// The format of a source image, the ones supported by the `image` crate
+/// and the ones decoded with other crates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Image(ImageFormat),
+    Svg,
+    Heif,
+    Jxl,
+}
+
+impl InputFormat {
+    /// Returns the format of a mime type like `image/png`, parameters like `; charset=utf-8` are ignored.
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        let essence = mime_type.split(';').next().unwrap_or_default().trim();
+
+        match essence.to_ascii_lowercase().as_str() {
+            "image/svg+xml" => Some(Self::Svg),
+            "image/heic" | "image/heif" | "image/heic-sequence" | "image/heif-sequence" => {
+                Some(Self::Heif)
+            }
+            "image/jxl" => Some(Self::Jxl),
+            essence => ImageFormat::from_mime_type(essence).map(Self::Image),
+        }
+    }
+
+    /// Returns the format of a file extension or a mime subtype like `png` or `svg+xml`.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "svg" | "svgz" | "svg+xml" => Some(Self::Svg),
+            "heic" | "heif" | "hif" => Some(Self::Heif),
+            "jxl" => Some(Self::Jxl),
+            extension => ImageFormat::from_extension(extension).map(Self::Image),
+        }
+    }
+
+    /// Detects the format from the first bytes of the image.
+    pub fn from_bytes(buffer: &[u8]) -> Option<Self> {
+        // JPEG XL codestream or ISOBMFF container
+        if buffer.starts_with(&[0xFF, 0x0A]) || buffer.starts_with(b"\0\0\0\x0CJXL \r\n\x87\n") {
+            return Some(Self::Jxl);
+        }
+
+        if let Some(brands) = ftyp_brands(buffer) {
+            // AVIF files can use the generic HEIF brands as the major brand, like `mif1`
+            if brands
+                .clone()
+                .any(|brand| brand == b"avif" || brand == b"avis")
+            {
+                return Some(Self::Image(ImageFormat::Avif));
+            }
+
+            if let Some(
+                b"heic" | b"heix" | b"hevc" | b"hevx" | b"heim" | b"heis" | b"mif1" | b"msf1",
+            ) = buffer.get(8..12)
+            {
+                return Some(Self::Heif);
+            }
+        }
+
+        if let Ok(format) = image::guess_format(buffer) {
+            return Some(Self::Image(format));
+        }
+
+        // SVGZ is the only gzip compressed format supported
+        if buffer.starts_with(&[0x1F, 0x8B]) {
+            return Some(Self::Svg);
+        }
+
+        let head = &buffer[..buffer.len().min(SVG_SNIFF_LEN)];
+        let head = String::from_utf8_lossy(head);
+        let head = head.trim_start_matches('\u{FEFF}').trim_start();
+        if head.starts_with('<') && head.contains("<svg") {
+            return Some(Self::Svg);
+        }
+
+        None
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Image(format) => format.extensions_str()[0],
+            Self::Svg => "svg",
+            Self::Heif => "heic",
+            Self::Jxl => "jxl",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Image(format) => image_mime_type(*format),
+            Self::Svg => "image/svg+xml",
+            Self::Heif => "image/heic",
+            Self::Jxl => "image/jxl",
+        }
+    }
+}
+
+/// Returns the registered mime type of a format, the extension isn't always the subtype,
+/// like `jpg` for `image/jpeg` or `ico` for `image/x-icon`.
+pub fn image_mime_type(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Jpeg => "image/jpeg",
+        ImageFormat::Png => "image/png",
+        ImageFormat::Gif => "image/gif",
+        ImageFormat::WebP => "image/webp",
+        ImageFormat::Avif => "image/avif",
+        ImageFormat::Tiff => "image/tiff",
+        ImageFormat::Ico => "image/x-icon",
+        ImageFormat::Bmp => "image/bmp",
+        ImageFormat::Pnm => "image/x-portable-anymap",
+        ImageFormat::Tga => "image/x-tga",
+        ImageFormat::Dds => "image/vnd-ms.dds",
+        ImageFormat::Hdr => "image/vnd.radiance",
+        ImageFormat::OpenExr => "image/x-exr",
+        ImageFormat::Farbfeld => "image/x-farbfeld",
+        ImageFormat::Qoi => "image/x-qoi",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Returns the major and the compatible brands of the `ftyp` box of an ISOBMFF file.
+fn ftyp_brands(buffer: &[u8]) -> Option<impl Iterator<Item = &[u8]> + Clone> {
+    if buffer.get(4..8) != Some(b"ftyp") {
+        return None;
+    }
+
+    let box_len = u32::from_be_bytes(buffer.get(..4)?.try_into().ok()?) as usize;
+    let ftyp = buffer.get(..box_len.min(buffer.len()))?;
+    let major = ftyp.get(8..12)?;
+    // The minor version is skipped
+    let compatible = ftyp.get(16..).unwrap_or_default().chunks_exact(4);
+
+    Some(std::iter::once(major).chain(compatible))
+}
+
+impl From<ImageFormat> for InputFormat {
+    fn from(format: ImageFormat) -> Self {
+        Self::Image(format)
+    }
+}
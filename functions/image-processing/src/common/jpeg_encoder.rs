@@ -0,0 +1,67 @@
+use super::ImageHandlerOptions;
+use crate::error::ResponseError;
+use image::DynamicImage;
+use lambda_runtime::Error;
+
+const DEFAULT_QUALITY: u8 = 100;
+
+/// Encodes the image as JPEG using `mozjpeg`, with trellis quantization and optimized
+/// huffman tables, and optionally progressive scans and custom chroma subsampling.
+#[cfg(feature = "mozjpeg")]
+pub fn encode_jpeg(img: &DynamicImage, options: &ImageHandlerOptions) -> Result<Vec<u8>, Error> {
+    use super::ChromaSubsampling;
+    use mozjpeg::{ColorSpace, Compress};
+
+    let quality = options.quality.unwrap_or(DEFAULT_QUALITY);
+    let rgb = img.to_rgb8();
+    let (width, height) = rgb.dimensions();
+
+    // mozjpeg reports the errors by unwinding
+    let result = std::panic::catch_unwind(|| -> std::io::Result<Vec<u8>> {
+        let mut compress = Compress::new(ColorSpace::JCS_RGB);
+        compress.set_size(width as usize, height as usize);
+        compress.set_quality(quality as f32);
+
+        if options.progressive {
+            compress.set_progressive_mode();
+        } else {
+            compress.set_optimize_scans(false);
+        }
+
+        let (cb, cr) = match options.subsampling.unwrap_or(ChromaSubsampling::Yuv420) {
+            ChromaSubsampling::Yuv444 => ((1, 1), (1, 1)),
+            ChromaSubsampling::Yuv422 => ((2, 1), (2, 1)),
+            ChromaSubsampling::Yuv420 => ((2, 2), (2, 2)),
+        };
+        compress.set_chroma_sampling_pixel_sizes(cb, cr);
+
+        let mut started = compress.start_compress(Vec::new())?;
+        started.write_scanlines(rgb.as_raw())?;
+        started.finish()
+    });
+
+    match result {
+        Ok(Ok(buf)) => Ok(buf),
+        Ok(Err(err)) => Err(ResponseError::from_error(err).into()),
+        Err(_) => Err(ResponseError::from_error("failed to encode jpeg").into()),
+    }
+}
+
+/// Encodes the image as a baseline JPEG.
+#[cfg(not(feature = "mozjpeg"))]
+pub fn encode_jpeg(img: &DynamicImage, options: &ImageHandlerOptions) -> Result<Vec<u8>, Error> {
+    use super::write_image;
+    use image::ImageOutputFormat;
+    use reqwest::StatusCode;
+
+    if options.progressive || options.subsampling.is_some() {
+        return Err(ResponseError::new(
+            StatusCode::BAD_REQUEST,
+            "`progressive` and `subsampling` are not supported, the `mozjpeg` feature is not enabled",
+        )
+        .into());
+    }
+
+    let quality = options.quality.unwrap_or(DEFAULT_QUALITY);
+    write_image(img, ImageOutputFormat::Jpeg(quality))
+}
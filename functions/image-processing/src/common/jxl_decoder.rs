@@ -0,0 +1,80 @@
+use crate::error::ResponseError;
+use image::DynamicImage;
+use lambda_runtime::Error;
+
+/// Decodes the first frame of a JPEG XL image using the pure Rust `jxl-oxide` decoder,
+/// the colors are converted to sRGB.
+#[cfg(feature = "jxl")]
+pub fn decode_jxl(buffer: &[u8]) -> Result<DynamicImage, Error> {
+    use image::{ImageBuffer, Luma, LumaA, Rgb, Rgba};
+    use jxl_oxide::{EnumColourEncoding, JxlImage, PixelFormat, RenderingIntent};
+
+    let mut image = JxlImage::builder()
+        .read(buffer)
+        .map_err(ResponseError::from_error)?;
+
+    image.request_color_encoding(EnumColourEncoding::srgb(RenderingIntent::Relative));
+
+    let pixel_format = image.pixel_format();
+    let render = image.render_frame(0).map_err(ResponseError::from_error)?;
+    let mut stream = render.stream();
+    let (width, height) = (stream.width(), stream.height());
+
+    let mut pixels = vec![0u16; width as usize * height as usize * stream.channels() as usize];
+    stream.write_to_buffer(&mut pixels);
+
+    let img = match pixel_format {
+        PixelFormat::Gray => {
+            ImageBuffer::<Luma<u16>, _>::from_raw(width, height, pixels).map(DynamicImage::from)
+        }
+        PixelFormat::Graya => {
+            ImageBuffer::<LumaA<u16>, _>::from_raw(width, height, pixels).map(DynamicImage::from)
+        }
+        PixelFormat::Rgb => {
+            ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, pixels).map(DynamicImage::from)
+        }
+        PixelFormat::Rgba => {
+            ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, pixels).map(DynamicImage::from)
+        }
+        PixelFormat::Cmyk | PixelFormat::Cmyka => {
+            return Err(ResponseError::new(
+                reqwest::StatusCode::BAD_REQUEST,
+                "CMYK JPEG XL images are not supported",
+            )
+            .into())
+        }
+    };
+
+    img.ok_or_else(|| Error::from("failed to read the JPEG XL image"))
+}
+
+/// Returns the size of a JPEG XL image reading only its header.
+#[cfg(feature = "jxl")]
+pub fn jxl_size(buffer: &[u8]) -> Result<(u32, u32), Error> {
+    let image = jxl_oxide::JxlImage::builder()
+        .read(buffer)
+        .map_err(ResponseError::from_error)?;
+
+    Ok((image.width(), image.height()))
+}
+
+#[cfg(not(feature = "jxl"))]
+pub fn decode_jxl(_buffer: &[u8]) -> Result<DynamicImage, Error> {
+    Err(jxl_disabled())
+}
+
+#[cfg(not(feature = "jxl"))]
+pub fn jxl_size(_buffer: &[u8]) -> Result<(u32, u32), Error> {
+    Err(jxl_disabled())
+}
+
+#[cfg(not(feature = "jxl"))]
+fn jxl_disabled() -> Error {
+    use reqwest::StatusCode;
+
+    ResponseError::new(
+        StatusCode::BAD_REQUEST,
+        "jxl input is not supported, the `jxl` feature is not enabled",
+    )
+    .into()
+}
@@ -0,0 +1,75 @@
+use super::Mask;
+use image::{DynamicImage, RgbaImage};
+
+/// Applies the mask to the image, the pixels outside of the mask are transparent.
+///
+/// `Circle` crops the image to a centered square before masking.
+pub fn apply_mask(img: &DynamicImage, mask: Mask) -> DynamicImage {
+    let img = match mask {
+        Mask::Circle => {
+            let size = img.width().min(img.height());
+            let x = (img.width() - size) / 2;
+            let y = (img.height() - size) / 2;
+            img.crop_imm(x, y, size, size)
+        }
+        Mask::Ellipse => img.clone(),
+    };
+
+    let mut rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let a = width as f64 / 2.0;
+    let b = height as f64 / 2.0;
+
+    for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+        let dx = (x as f64 + 0.5 - a) / a;
+        let dy = (y as f64 + 0.5 - b) / b;
+
+        // Approximated distance in pixels to the edge of the ellipse
+        let distance = ((dx * dx + dy * dy).sqrt() - 1.0) * a.min(b);
+        pixel[3] = scale_alpha(pixel[3], coverage(distance));
+    }
+
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Rounds the corners of the image with the given radius in pixels.
+pub fn round_corners(img: &DynamicImage, radius: u32) -> DynamicImage {
+    let mut rgba: RgbaImage = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let radius = radius.min(width / 2).min(height / 2);
+
+    if radius == 0 {
+        return DynamicImage::ImageRgba8(rgba);
+    }
+
+    let r = radius as f64;
+    for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+        let in_left = x < radius;
+        let in_right = x >= width - radius;
+        let in_top = y < radius;
+        let in_bottom = y >= height - radius;
+
+        if !((in_left || in_right) && (in_top || in_bottom)) {
+            continue;
+        }
+
+        let cx = if in_left { r } else { (width - radius) as f64 };
+        let cy = if in_top { r } else { (height - radius) as f64 };
+        let dx = x as f64 + 0.5 - cx;
+        let dy = y as f64 + 0.5 - cy;
+
+        let distance = (dx * dx + dy * dy).sqrt() - r;
+        pixel[3] = scale_alpha(pixel[3], coverage(distance));
+    }
+
+    DynamicImage::ImageRgba8(rgba)
+}
+
+// Anti-aliasing, fraction of the pixel inside of the shape given its distance to the edge
+fn coverage(distance: f64) -> f64 {
+    (0.5 - distance).clamp(0.0, 1.0)
+}
+
+fn scale_alpha(alpha: u8, coverage: f64) -> u8 {
+    (alpha as f64 * coverage).round() as u8
+}
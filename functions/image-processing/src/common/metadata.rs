@@ -0,0 +1,787 @@
+use super::MetadataMode;
+use exif::{experimental::Writer, Context, In, Reader, Tag};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use image::ImageFormat;
+use lambda_runtime::Error;
+use roxmltree::Document;
+use std::io::{Cursor, Read, Write};
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+const EXIF_NS: &str = "http://ns.adobe.com/exif/1.0/";
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+const PHOTOSHOP_HEADER: &[u8] = b"Photoshop 3.0\0";
+const IPTC_RESOURCE_ID: u16 = 0x0404;
+
+/// Max payload of a JPEG marker segment, the length field counts itself.
+const MAX_SEGMENT_LEN: usize = u16::MAX as usize - 2;
+
+/// The metadata carried from the source image to the output.
+#[derive(Debug, Default)]
+pub struct Metadata {
+    pub icc: Option<Vec<u8>>,
+    /// Raw TIFF structure, without the `Exif\0\0` header.
+    pub exif: Option<Vec<u8>>,
+    pub xmp: Option<Vec<u8>>,
+    /// IPTC-NAA records, only stored in JPEG images.
+    pub iptc: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.icc.is_none() && self.exif.is_none() && self.xmp.is_none() && self.iptc.is_none()
+    }
+
+    /// Bytes taken by the metadata, without the container overhead.
+    pub fn len(&self) -> usize {
+        [&self.icc, &self.exif, &self.xmp, &self.iptc]
+            .iter()
+            .filter_map(|data| data.as_ref().map(Vec::len))
+            .sum()
+    }
+
+    /// Keeps the metadata for the given mode, GPS data is removed unless `keep_gps` is set.
+    pub fn filter(mut self, mode: MetadataMode, keep_gps: bool) -> Self {
+        match mode {
+            MetadataMode::Strip => return Self::default(),
+            MetadataMode::Icc => {
+                self.exif = None;
+                self.xmp = None;
+                self.iptc = None;
+            }
+            MetadataMode::Keep => {}
+        }
+
+        if !keep_gps {
+            self.exif = self.exif.and_then(strip_gps);
+            self.xmp = self.xmp.and_then(strip_xmp_gps);
+        }
+
+        self
+    }
+}
+
+/// Reads the metadata of a JPEG, PNG or WebP image without decoding the image.
+pub fn read_metadata(buffer: &[u8], format: ImageFormat) -> Metadata {
+    match format {
+        ImageFormat::Jpeg => read_jpeg(buffer),
+        ImageFormat::Png => read_png(buffer),
+        ImageFormat::WebP => read_webp(buffer),
+        _ => Metadata::default(),
+    }
+}
+
+/// Adds the metadata to an encoded JPEG, PNG or WebP image, other formats are returned as is.
+pub fn embed_metadata(
+    buf: Vec<u8>,
+    format: ImageFormat,
+    metadata: &Metadata,
+) -> Result<Vec<u8>, Error> {
+    if metadata.is_empty() {
+        return Ok(buf);
+    }
+
+    match format {
+        ImageFormat::Jpeg => Ok(embed_jpeg(buf, metadata)),
+        ImageFormat::Png => embed_png(buf, metadata),
+        ImageFormat::WebP => Ok(embed_webp(buf, metadata)),
+        _ => Ok(buf),
+    }
+}
+
+// Rewrites the EXIF data without the GPS IFD and the thumbnail
+fn strip_gps(exif: Vec<u8>) -> Option<Vec<u8>> {
+    let exif = match Reader::new().read_raw(exif) {
+        Ok(exif) => exif,
+        Err(e) => {
+            tracing::warn!("dropping unreadable EXIF data: {e}");
+            return None;
+        }
+    };
+
+    let mut writer = Writer::new();
+    let mut fields = 0;
+    for field in exif.fields() {
+        if field.ifd_num == In::PRIMARY && !matches!(field.tag, Tag(Context::Gps, _)) {
+            writer.push_field(field);
+            fields += 1;
+        }
+    }
+
+    if fields == 0 {
+        return None;
+    }
+
+    let mut cursor = Cursor::new(Vec::new());
+    match writer.write(&mut cursor, exif.little_endian()) {
+        Ok(()) => Some(cursor.into_inner()),
+        Err(e) => {
+            tracing::warn!("dropping EXIF data that can't be rewritten: {e}");
+            None
+        }
+    }
+}
+
+// Removes the `exif:GPS*` properties, written as elements or as attributes of `rdf:Description`
+fn strip_xmp_gps(xmp: Vec<u8>) -> Option<Vec<u8>> {
+    let has_gps = |xmp: &[u8]| xmp.windows(3).any(|w| w == b"GPS");
+    if !has_gps(&xmp) {
+        return Some(xmp);
+    }
+
+    // A packet that can't be edited is dropped, it may contain the location
+    let Some(text) = std::str::from_utf8(&xmp).ok() else {
+        tracing::warn!("dropping XMP data with GPS properties that isn't UTF-8");
+        return None;
+    };
+
+    let doc = match Document::parse(text) {
+        Ok(doc) => doc,
+        Err(e) => {
+            tracing::warn!("dropping unreadable XMP data with GPS properties: {e}");
+            return None;
+        }
+    };
+
+    let is_gps =
+        |namespace: Option<&str>, name: &str| namespace == Some(EXIF_NS) && name.starts_with("GPS");
+
+    let mut ranges = Vec::new();
+    for node in doc.descendants().filter(|node| node.is_element()) {
+        if is_gps(node.tag_name().namespace(), node.tag_name().name()) {
+            ranges.push(node.range());
+            continue;
+        }
+
+        for attribute in node.attributes() {
+            if is_gps(attribute.namespace(), attribute.name()) {
+                // The whitespace before the attribute is removed with it
+                let range = attribute.range();
+                let start = text[..range.start].trim_end().len();
+                ranges.push(start..range.end);
+            }
+        }
+    }
+
+    // The ranges are sorted, the ones inside a removed element are skipped
+    let mut stripped = String::with_capacity(text.len());
+    let mut end = 0;
+    for range in ranges {
+        if range.start < end {
+            continue;
+        }
+
+        stripped.push_str(&text[end..range.start]);
+        end = range.end;
+    }
+
+    stripped.push_str(&text[end..]);
+    Some(stripped.into_bytes())
+}
+
+fn read_jpeg(buffer: &[u8]) -> Metadata {
+    let mut metadata = Metadata::default();
+    let mut icc_chunks = Vec::new();
+
+    for (marker, data) in jpeg_segments(buffer) {
+        match marker {
+            0xE1 if data.starts_with(EXIF_HEADER) => {
+                metadata.exif = Some(data[EXIF_HEADER.len()..].to_vec());
+            }
+            0xE1 if data.starts_with(XMP_HEADER) => {
+                metadata.xmp = Some(data[XMP_HEADER.len()..].to_vec());
+            }
+            // The profile can be split in several segments: header, sequence number, count
+            0xE2 if data.len() > ICC_HEADER.len() + 2 && data.starts_with(ICC_HEADER) => {
+                let seq = data[ICC_HEADER.len()];
+                icc_chunks.push((seq, &data[ICC_HEADER.len() + 2..]));
+            }
+            0xED if data.starts_with(PHOTOSHOP_HEADER) => {
+                metadata.iptc = photoshop_iptc(&data[PHOTOSHOP_HEADER.len()..]);
+            }
+            _ => {}
+        }
+    }
+
+    if !icc_chunks.is_empty() {
+        icc_chunks.sort_by_key(|(seq, _)| *seq);
+        metadata.icc = Some(
+            icc_chunks
+                .into_iter()
+                .flat_map(|(_, c)| c)
+                .copied()
+                .collect(),
+        );
+    }
+
+    metadata
+}
+
+// Photoshop image resources: signature, id, padded Pascal string name, size, padded data
+fn photoshop_iptc(mut resources: &[u8]) -> Option<Vec<u8>> {
+    while resources.len() >= 8 && resources.starts_with(b"8BIM") {
+        let id = u16::from_be_bytes([resources[4], resources[5]]);
+        let name_len = resources[6] as usize;
+        let size_at = 6 + (name_len + 2) / 2 * 2;
+        let size = resources.get(size_at..size_at + 4)?;
+        let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+        let data = resources.get(size_at + 4..size_at + 4 + size)?;
+
+        if id == IPTC_RESOURCE_ID {
+            return Some(data.to_vec());
+        }
+
+        resources = resources.get(size_at + 4 + size + size % 2..)?;
+    }
+
+    None
+}
+
+// The marker segments before the compressed data
+fn jpeg_segments(buffer: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut segments = Vec::new();
+    let mut pos = 2;
+
+    while let Some([0xFF, marker]) = buffer.get(pos..pos + 2) {
+        let marker = *marker;
+        pos += 2;
+
+        match marker {
+            // Fill bytes
+            0xFF => pos -= 1,
+            // Standalone markers without length
+            0x01 | 0xD0..=0xD7 => {}
+            // Start of scan or end of image
+            0xDA | 0xD9 => break,
+            _ => {
+                let Some(len) = buffer.get(pos..pos + 2) else {
+                    break;
+                };
+                let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+                let Some(data) = buffer.get(pos + 2..pos + len.max(2)) else {
+                    break;
+                };
+                segments.push((marker, data));
+                pos += len.max(2);
+            }
+        }
+    }
+
+    segments
+}
+
+fn embed_jpeg(buf: Vec<u8>, metadata: &Metadata) -> Vec<u8> {
+    // The new segments go after SOI and the JFIF segment, that must be the first one
+    let mut insert_at = 2;
+    if let Some((0xE0, data)) = jpeg_segments(&buf).first() {
+        insert_at += 4 + data.len();
+    }
+
+    let mut segments = Vec::with_capacity(metadata.len() + 64);
+
+    if let Some(exif) = &metadata.exif {
+        if EXIF_HEADER.len() + exif.len() <= MAX_SEGMENT_LEN {
+            write_jpeg_segment(&mut segments, 0xE1, &[EXIF_HEADER, exif]);
+        } else {
+            tracing::warn!("EXIF data too big for a JPEG segment, skipping it");
+        }
+    }
+
+    if let Some(xmp) = &metadata.xmp {
+        if XMP_HEADER.len() + xmp.len() <= MAX_SEGMENT_LEN {
+            write_jpeg_segment(&mut segments, 0xE1, &[XMP_HEADER, xmp]);
+        } else {
+            tracing::warn!("XMP packet too big for a JPEG segment, skipping it");
+        }
+    }
+
+    if let Some(iptc) = &metadata.iptc {
+        // A single resource with an empty name
+        let mut resource = b"8BIM".to_vec();
+        resource.extend_from_slice(&IPTC_RESOURCE_ID.to_be_bytes());
+        resource.extend_from_slice(&[0, 0]);
+        resource.extend_from_slice(&(iptc.len() as u32).to_be_bytes());
+        resource.extend_from_slice(iptc);
+        if iptc.len() % 2 == 1 {
+            resource.push(0);
+        }
+
+        if PHOTOSHOP_HEADER.len() + resource.len() <= MAX_SEGMENT_LEN {
+            write_jpeg_segment(&mut segments, 0xED, &[PHOTOSHOP_HEADER, &resource]);
+        } else {
+            tracing::warn!("IPTC data too big for a JPEG segment, skipping it");
+        }
+    }
+
+    if let Some(icc) = &metadata.icc {
+        let chunks: Vec<_> = icc.chunks(MAX_SEGMENT_LEN - ICC_HEADER.len() - 2).collect();
+        if chunks.len() <= u8::MAX as usize {
+            let count = chunks.len() as u8;
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                let seq = [i as u8 + 1, count];
+                write_jpeg_segment(&mut segments, 0xE2, &[ICC_HEADER, &seq, chunk]);
+            }
+        } else {
+            tracing::warn!("ICC profile too big for a JPEG image, skipping it");
+        }
+    }
+
+    let mut buf = buf;
+    buf.splice(insert_at..insert_at, segments);
+    buf
+}
+
+fn write_jpeg_segment(out: &mut Vec<u8>, marker: u8, parts: &[&[u8]]) {
+    let len: usize = parts.iter().map(|p| p.len()).sum::<usize>() + 2;
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&(len as u16).to_be_bytes());
+    parts.iter().for_each(|p| out.extend_from_slice(p));
+}
+
+fn read_png(buffer: &[u8]) -> Metadata {
+    let mut metadata = Metadata::default();
+
+    for (kind, data) in png_chunks(buffer) {
+        match kind {
+            // Profile name, null separator, compression method, zlib stream
+            b"iCCP" => {
+                let compressed = data
+                    .iter()
+                    .position(|b| *b == 0)
+                    .and_then(|name_end| data.get(name_end + 2..));
+                metadata.icc = compressed.and_then(|c| inflate(c).ok());
+            }
+            b"eXIf" => metadata.exif = Some(data.to_vec()),
+            b"iTXt" if data.starts_with(PNG_XMP_KEYWORD) => {
+                metadata.xmp = read_png_itxt(&data[PNG_XMP_KEYWORD.len()..]);
+            }
+            _ => {}
+        }
+    }
+
+    metadata
+}
+
+// Null separator, compression flag, compression method, language and translated keyword
+// (both null terminated), text
+fn read_png_itxt(data: &[u8]) -> Option<Vec<u8>> {
+    let [0, compressed, _, rest @ ..] = data else {
+        return None;
+    };
+
+    let lang_end = rest.iter().position(|b| *b == 0)?;
+    let rest = &rest[lang_end + 1..];
+    let keyword_end = rest.iter().position(|b| *b == 0)?;
+    let text = &rest[keyword_end + 1..];
+
+    match *compressed {
+        0 => Some(text.to_vec()),
+        _ => inflate(text).ok(),
+    }
+}
+
+fn png_chunks(buffer: &[u8]) -> Vec<(&[u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut pos = 8;
+
+    // Length, type, data, CRC
+    while let Some(header) = buffer.get(pos..pos + 8) {
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind: &[u8; 4] = header[4..].try_into().unwrap();
+        let Some(data) = buffer.get(pos + 8..pos + 8 + len) else {
+            break;
+        };
+
+        chunks.push((kind, data));
+        if kind == b"IEND" {
+            break;
+        }
+        pos += 12 + len;
+    }
+
+    chunks
+}
+
+fn embed_png(buf: Vec<u8>, metadata: &Metadata) -> Result<Vec<u8>, Error> {
+    let mut chunks = Vec::with_capacity(metadata.len() + 64);
+
+    if let Some(icc) = &metadata.icc {
+        write_png_chunk(&mut chunks, b"iCCP", &[b"ICC Profile\0\0", &deflate(icc)?]);
+    }
+
+    if let Some(exif) = &metadata.exif {
+        write_png_chunk(&mut chunks, b"eXIf", &[exif]);
+    }
+
+    if let Some(xmp) = &metadata.xmp {
+        // Uncompressed, without language or translated keyword
+        write_png_chunk(&mut chunks, b"iTXt", &[PNG_XMP_KEYWORD, b"\0\0\0\0\0", xmp]);
+    }
+
+    // Signature (8) and IHDR (25), the metadata chunks must be before the image data
+    let insert_at = 8 + 25;
+    let mut buf = buf;
+    buf.splice(insert_at..insert_at, chunks);
+    Ok(buf)
+}
+
+fn write_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], parts: &[&[u8]]) {
+    let len: usize = parts.iter().map(|p| p.len()).sum();
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    parts.iter().for_each(|p| crc.update(p));
+
+    out.extend_from_slice(&(len as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    parts.iter().for_each(|p| out.extend_from_slice(p));
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+fn inflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+fn deflate(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn read_webp(buffer: &[u8]) -> Metadata {
+    let mut metadata = Metadata::default();
+
+    for (kind, data) in webp_chunks(buffer) {
+        match kind {
+            b"ICCP" => metadata.icc = Some(data.to_vec()),
+            // Some encoders keep the JPEG header in the chunk
+            b"EXIF" => {
+                let data = data.strip_prefix(EXIF_HEADER).unwrap_or(data);
+                metadata.exif = Some(data.to_vec());
+            }
+            b"XMP " => metadata.xmp = Some(data.to_vec()),
+            _ => {}
+        }
+    }
+
+    metadata
+}
+
+fn webp_chunks(buffer: &[u8]) -> Vec<(&[u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut pos = 12;
+
+    // FourCC, size (little endian), data, padding to an even size
+    while let Some(header) = buffer.get(pos..pos + 8) {
+        let kind: &[u8; 4] = header[..4].try_into().unwrap();
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let Some(data) = buffer.get(pos + 8..pos + 8 + len) else {
+            break;
+        };
+
+        chunks.push((kind, data));
+        pos += 8 + len + len % 2;
+    }
+
+    chunks
+}
+
+fn embed_webp(buf: Vec<u8>, metadata: &Metadata) -> Vec<u8> {
+    const ICC_FLAG: u8 = 0x20;
+    const ALPHA_FLAG: u8 = 0x10;
+    const EXIF_FLAG: u8 = 0x08;
+    const XMP_FLAG: u8 = 0x04;
+
+    let chunks: Vec<_> = webp_chunks(&buf)
+        .into_iter()
+        .filter(|(kind, _)| !matches!(*kind, b"ICCP" | b"EXIF" | b"XMP "))
+        .collect();
+
+    // Simple WebP files must be converted to the extended format to have metadata
+    let mut vp8x = match chunks.first() {
+        Some((b"VP8X", data)) if data.len() == 10 => data.to_vec(),
+        Some((kind, data)) => match webp_canvas(kind, data) {
+            Some((width, height, alpha)) => {
+                let mut vp8x = vec![if alpha { ALPHA_FLAG } else { 0 }, 0, 0, 0];
+                vp8x.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+                vp8x.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+                vp8x
+            }
+            None => return buf,
+        },
+        None => return buf,
+    };
+
+    let mut out = Vec::with_capacity(buf.len() + metadata.len() + 64);
+    out.extend_from_slice(b"RIFF\0\0\0\0WEBP");
+
+    vp8x[0] &= !(ICC_FLAG | EXIF_FLAG | XMP_FLAG);
+    if metadata.icc.is_some() {
+        vp8x[0] |= ICC_FLAG;
+    }
+    if metadata.exif.is_some() {
+        vp8x[0] |= EXIF_FLAG;
+    }
+    if metadata.xmp.is_some() {
+        vp8x[0] |= XMP_FLAG;
+    }
+
+    // The ICC profile goes after VP8X and EXIF and XMP at the end
+    write_webp_chunk(&mut out, b"VP8X", &vp8x);
+    if let Some(icc) = &metadata.icc {
+        write_webp_chunk(&mut out, b"ICCP", icc);
+    }
+
+    let image_chunks = chunks.iter().filter(|(kind, _)| *kind != b"VP8X");
+    for (kind, data) in image_chunks {
+        write_webp_chunk(&mut out, kind, data);
+    }
+
+    if let Some(exif) = &metadata.exif {
+        write_webp_chunk(&mut out, b"EXIF", exif);
+    }
+    if let Some(xmp) = &metadata.xmp {
+        write_webp_chunk(&mut out, b"XMP ", xmp);
+    }
+
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    out
+}
+
+// Width, height and alpha of a simple lossy (VP8) or lossless (VP8L) image
+fn webp_canvas(kind: &[u8; 4], data: &[u8]) -> Option<(u32, u32, bool)> {
+    match kind {
+        // Frame tag (3), start code (3), width and height (14 bits each, little endian)
+        b"VP8 " => {
+            let dims = data.get(6..10)?;
+            let width = u16::from_le_bytes([dims[0], dims[1]]) & 0x3FFF;
+            let height = u16::from_le_bytes([dims[2], dims[3]]) & 0x3FFF;
+            Some((width as u32, height as u32, false)).filter(|_| width > 0 && height > 0)
+        }
+        // Signature (1), width - 1 and height - 1 (14 bits each), alpha used (1 bit)
+        b"VP8L" => {
+            let bits = data.get(1..5)?;
+            let bits = u32::from_le_bytes([bits[0], bits[1], bits[2], bits[3]]);
+            let width = (bits & 0x3FFF) + 1;
+            let height = ((bits >> 14) & 0x3FFF) + 1;
+            Some((width, height, bits >> 28 & 1 == 1))
+        }
+        _ => None,
+    }
+}
+
+fn write_webp_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(kind);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::{Field, Rational, Value};
+    use image::{codecs::jpeg::JpegEncoder, ImageOutputFormat, RgbImage};
+
+    const XMP: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"><rdf:Description xmlns:exif="http://ns.adobe.com/exif/1.0/" xmlns:dc="http://purl.org/dc/elements/1.1/" exif:GPSLatitude="34,36.0S" exif:ExposureTime="1/100"><exif:GPSLongitude>58,22.5W</exif:GPSLongitude><dc:creator>Jane Doe</dc:creator></rdf:Description></rdf:RDF></x:xmpmeta>"#;
+
+    fn image() -> RgbImage {
+        RgbImage::from_fn(16, 12, |x, y| image::Rgb([x as u8 * 16, y as u8 * 20, 128]))
+    }
+
+    fn encode(format: ImageOutputFormat) -> Vec<u8> {
+        let mut buf = Vec::new();
+        image()
+            .write_to(&mut Cursor::new(&mut buf), format)
+            .unwrap();
+        buf
+    }
+
+    fn jpeg() -> Vec<u8> {
+        let mut buf = Vec::new();
+        JpegEncoder::new(&mut buf).encode_image(&image()).unwrap();
+        buf
+    }
+
+    fn webp(lossless: bool) -> Vec<u8> {
+        let img = image();
+        let encoder = webp::Encoder::from_rgb(&img, img.width(), img.height());
+        match lossless {
+            true => encoder.encode_lossless().to_vec(),
+            false => encoder.encode(80.0).to_vec(),
+        }
+    }
+
+    fn exif_with_gps() -> Vec<u8> {
+        let fields = [
+            Field {
+                tag: Tag::ImageDescription,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"Sunset".to_vec()]),
+            },
+            Field {
+                tag: Tag::GPSLatitudeRef,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"S".to_vec()]),
+            },
+            Field {
+                tag: Tag::GPSLatitude,
+                ifd_num: In::PRIMARY,
+                value: Value::Rational(vec![
+                    Rational { num: 34, denom: 1 },
+                    Rational { num: 36, denom: 1 },
+                    Rational { num: 0, denom: 1 },
+                ]),
+            },
+        ];
+
+        let mut writer = Writer::new();
+        fields.iter().for_each(|field| writer.push_field(field));
+        let mut cursor = Cursor::new(Vec::new());
+        writer.write(&mut cursor, false).unwrap();
+        cursor.into_inner()
+    }
+
+    fn exif_tags(exif: &[u8]) -> Vec<Tag> {
+        let exif = Reader::new().read_raw(exif.to_vec()).unwrap();
+        exif.fields().map(|field| field.tag).collect()
+    }
+
+    fn metadata(iptc: bool) -> Metadata {
+        Metadata {
+            icc: Some((0..=255).cycle().take(600).collect()),
+            exif: Some(exif_with_gps()),
+            xmp: Some(XMP.as_bytes().to_vec()),
+            iptc: iptc.then(|| vec![0x1C, 0x02, 0x78, 0x00, 0x05, b'B', b'e', b'a', b'c', b'h']),
+        }
+    }
+
+    fn assert_round_trip(buf: Vec<u8>, format: ImageFormat, expected: Metadata) {
+        let embedded = embed_metadata(buf, format, &expected).unwrap();
+        image::load_from_memory_with_format(&embedded, format).unwrap();
+
+        let metadata = read_metadata(&embedded, format);
+        assert_eq!(metadata.icc, expected.icc);
+        assert_eq!(metadata.exif, expected.exif);
+        assert_eq!(metadata.xmp, expected.xmp);
+        assert_eq!(metadata.iptc, expected.iptc);
+    }
+
+    #[test]
+    fn round_trips_jpeg_metadata() {
+        assert_round_trip(jpeg(), ImageFormat::Jpeg, metadata(true));
+    }
+
+    #[test]
+    fn round_trips_jpeg_icc_profile_split_in_segments() {
+        let expected = Metadata {
+            icc: Some((0..=255).cycle().take(MAX_SEGMENT_LEN * 2).collect()),
+            ..Default::default()
+        };
+
+        assert_round_trip(jpeg(), ImageFormat::Jpeg, expected);
+    }
+
+    #[test]
+    fn round_trips_png_metadata() {
+        assert_round_trip(
+            encode(ImageOutputFormat::Png),
+            ImageFormat::Png,
+            metadata(false),
+        );
+    }
+
+    #[test]
+    fn round_trips_lossy_webp_metadata() {
+        assert_round_trip(webp(false), ImageFormat::WebP, metadata(false));
+    }
+
+    #[test]
+    fn round_trips_lossless_webp_metadata() {
+        assert_round_trip(webp(true), ImageFormat::WebP, metadata(false));
+    }
+
+    #[test]
+    fn replaces_the_metadata_of_extended_webp() {
+        let first = embed_metadata(webp(false), ImageFormat::WebP, &metadata(false)).unwrap();
+        let icc_only = Metadata {
+            icc: Some(vec![1, 2, 3]),
+            ..Default::default()
+        };
+
+        assert_round_trip(first, ImageFormat::WebP, icc_only);
+    }
+
+    #[test]
+    fn strips_gps_from_exif() {
+        let metadata = metadata(true).filter(MetadataMode::Keep, false);
+
+        assert_eq!(exif_tags(&metadata.exif.unwrap()), [Tag::ImageDescription]);
+    }
+
+    #[test]
+    fn keeps_gps_in_exif_with_keep_gps() {
+        let metadata = metadata(true).filter(MetadataMode::Keep, true);
+
+        assert_eq!(
+            exif_tags(&metadata.exif.unwrap()),
+            [Tag::ImageDescription, Tag::GPSLatitudeRef, Tag::GPSLatitude]
+        );
+    }
+
+    #[test]
+    fn drops_exif_with_only_gps() {
+        let exif = exif_with_gps();
+        let exif = Reader::new().read_raw(exif).unwrap();
+        let gps = exif
+            .fields()
+            .filter(|field| matches!(field.tag, Tag(Context::Gps, _)));
+
+        let mut writer = Writer::new();
+        gps.for_each(|field| writer.push_field(field));
+        let mut cursor = Cursor::new(Vec::new());
+        writer.write(&mut cursor, false).unwrap();
+
+        assert_eq!(strip_gps(cursor.into_inner()), None);
+    }
+
+    #[test]
+    fn strips_gps_elements_and_attributes_from_xmp() {
+        let xmp = strip_xmp_gps(XMP.as_bytes().to_vec()).unwrap();
+        let xmp = String::from_utf8(xmp).unwrap();
+
+        assert!(!xmp.contains("GPS"), "{xmp}");
+        assert!(xmp.contains(r#"exif:ExposureTime="1/100""#), "{xmp}");
+        assert!(xmp.contains("<dc:creator>Jane Doe</dc:creator>"), "{xmp}");
+        Document::parse(&xmp).unwrap();
+    }
+
+    #[test]
+    fn keeps_gps_properties_of_other_namespaces_in_xmp() {
+        let xmp = r#"<rdf:Description xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns:custom="http://example.com/" custom:GPSNote="kept"/>"#;
+
+        assert_eq!(
+            strip_xmp_gps(xmp.as_bytes().to_vec()).unwrap(),
+            xmp.as_bytes()
+        );
+    }
+
+    #[test]
+    fn drops_unreadable_xmp_with_gps() {
+        assert_eq!(strip_xmp_gps(b"<exif:GPSLatitude>".to_vec()), None);
+    }
+
+    #[test]
+    fn filters_by_mode() {
+        let icc = metadata(true).filter(MetadataMode::Icc, false);
+        assert!(icc.icc.is_some());
+        assert!(icc.exif.is_none() && icc.xmp.is_none() && icc.iptc.is_none());
+
+        assert!(metadata(true).filter(MetadataMode::Strip, true).is_empty());
+    }
+}
@@ -1,5 +1,56 @@
+mod animation;
+mod avif_encoder;
+mod canvas;
+mod color_profile;
+mod crop;
+mod encoder;
+mod heif_decoder;
+mod icons;
 mod image_handler;
+mod image_info;
+mod input_format;
+mod jpeg_encoder;
+mod jxl_decoder;
+mod mask;
+mod metadata;
+mod pages;
+mod png_encoder;
+mod smart_crop;
+mod svg;
+mod target_size;
+mod trim;
 mod types;
+mod validation;
+mod webp_encoder;
+mod zip_writer;
 
-pub use image_handler::{image_handler, ImageByteBuffer, ImageHandlerOptions};
-pub use types::{FlipImage, CropRect};
+pub use animation::{decode_animation, encode_gif, AnimatedImage};
+pub use avif_encoder::encode_avif;
+pub use canvas::{add_padding, extend_canvas};
+pub use color_profile::convert_to_srgb;
+pub use crop::CropOptions;
+pub use encoder::{encode_image, output_format, write_image};
+pub use heif_decoder::{decode_heif, heif_info};
+pub use icons::{encode_ico, encode_icons, IconSetBuffer, FAVICON_SIZES, MAX_ICON_SIZE};
+pub use image_handler::{
+    icon_handler, image_handler, resized_height, ImageByteBuffer, ImageHandlerOptions,
+};
+pub use image_info::{image_info, GpsInfo, ImageInfo, MetadataInfo};
+pub use input_format::{image_mime_type, InputFormat};
+pub use jpeg_encoder::encode_jpeg;
+pub use jxl_decoder::{decode_jxl, jxl_size};
+pub use mask::{apply_mask, round_corners};
+pub use metadata::{embed_metadata, read_metadata, Metadata};
+pub use pages::{check_single_page, extract_page, read_pages, PageInfo};
+pub use png_encoder::encode_png;
+pub use smart_crop::smart_crop_offset;
+pub use svg::{load_svg, render_svg};
+pub use target_size::encode_with_max_bytes;
+pub use trim::{trim_bounds, DEFAULT_TRIM_THRESHOLD};
+pub use types::{
+    AspectRatio, ChromaSubsampling, Color, ColorProfile, CropRect, Dimension, FlipImage, Gravity,
+    IconSet, Mask, MetadataMode, OutputFormat, Sides, Size, ValueList,
+};
+pub use validation::{validate_options, validate_outputs, Violations};
+pub use webp_encoder::{encode_animated_webp, encode_webp};
+pub use zip_writer::ZipWriter;
@@ -0,0 +1,473 @@
+use crate::error::ResponseError;
+use image::ImageFormat;
+use lambda_runtime::Error;
+use reqwest::StatusCode;
+use serde::Serialize;
+
+/// Limits the IFDs followed in a TIFF file, the chain can be long or even circular in broken files.
+const MAX_TIFF_PAGES: usize = 1024;
+
+const TIFF_IMAGE_WIDTH: u16 = 256;
+const TIFF_IMAGE_LENGTH: u16 = 257;
+
+const ICO_HEADER_LEN: usize = 6;
+const ICO_ENTRY_LEN: usize = 16;
+
+/// The size of a page of a multi-page TIFF or an entry of an ICO file.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PageInfo {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Returns the pages of a TIFF or the entries of an ICO file, `None` for other formats.
+pub fn read_pages(buffer: &[u8], format: ImageFormat) -> Option<Vec<PageInfo>> {
+    match format {
+        ImageFormat::Tiff => {
+            let tiff = Tiff::new(buffer)?;
+            let pages = tiff
+                .ifd_offsets()
+                .into_iter()
+                .filter_map(|ifd| tiff.page_info(ifd))
+                .collect();
+
+            Some(pages)
+        }
+        ImageFormat::Ico => {
+            let pages = ico_entries(buffer)?
+                .iter()
+                .map(|entry| PageInfo {
+                    width: entry.width,
+                    height: entry.height,
+                })
+                .collect();
+
+            Some(pages)
+        }
+        _ => None,
+    }
+}
+
+/// Returns a copy of the TIFF or ICO file that only contains the given page,
+/// so it can be decoded with `image::load`.
+///
+/// ICO files default to the largest entry, `None` is returned if the image can be decoded as is.
+pub fn extract_page(
+    buffer: &[u8],
+    format: ImageFormat,
+    page: Option<usize>,
+) -> Result<Option<Vec<u8>>, Error> {
+    match format {
+        ImageFormat::Tiff => {
+            let Some(tiff) = Tiff::new(buffer) else {
+                return check_single_page(page).map(|_| None);
+            };
+
+            // The first page is the one decoded by default
+            let index = page.unwrap_or(0);
+            let offsets = tiff.ifd_offsets();
+            let offset = *offsets
+                .get(index)
+                .ok_or_else(|| invalid_page(index, offsets.len()))?;
+
+            if index == 0 {
+                return Ok(None);
+            }
+
+            // Point the header to the IFD of the page
+            let mut page_buffer = buffer.to_vec();
+            page_buffer[4..8].copy_from_slice(&tiff.u32_bytes(offset as u32));
+            Ok(Some(page_buffer))
+        }
+        ImageFormat::Ico => {
+            let Some(entries) = ico_entries(buffer) else {
+                return check_single_page(page).map(|_| None);
+            };
+
+            let index = match page {
+                Some(index) if index >= entries.len() => {
+                    return Err(invalid_page(index, entries.len()))
+                }
+                Some(index) => index,
+                None => largest_ico_entry(&entries),
+            };
+
+            Ok(Some(ico_with_entry(buffer, &entries[index])?))
+        }
+        _ => check_single_page(page).map(|_| None),
+    }
+}
+
+/// Fails if a page other than the first is requested from a single page image.
+pub fn check_single_page(page: Option<usize>) -> Result<(), Error> {
+    match page.filter(|page| *page > 0) {
+        Some(page) => Err(ResponseError::new(
+            StatusCode::BAD_REQUEST,
+            format!("invalid `page` {page}, the image only has 1 page"),
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+fn invalid_page(page: usize, page_count: usize) -> Error {
+    ResponseError::new(
+        StatusCode::BAD_REQUEST,
+        format!("invalid `page` {page}, the image has {page_count} pages"),
+    )
+    .into()
+}
+
+struct Tiff<'a> {
+    buffer: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    /// Returns `None` if the buffer isn't a classic TIFF, BigTIFF files aren't supported.
+    fn new(buffer: &'a [u8]) -> Option<Self> {
+        let little_endian = match buffer.get(..4)? {
+            b"II*\0" => true,
+            b"MM\0*" => false,
+            _ => return None,
+        };
+
+        Some(Self {
+            buffer,
+            little_endian,
+        })
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes = self.buffer.get(offset..offset + 2)?.try_into().ok()?;
+        Some(match self.little_endian {
+            true => u16::from_le_bytes(bytes),
+            false => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes = self.buffer.get(offset..offset + 4)?.try_into().ok()?;
+        Some(match self.little_endian {
+            true => u32::from_le_bytes(bytes),
+            false => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32_bytes(&self, value: u32) -> [u8; 4] {
+        match self.little_endian {
+            true => value.to_le_bytes(),
+            false => value.to_be_bytes(),
+        }
+    }
+
+    /// Follows the chain of IFDs, one for each page.
+    fn ifd_offsets(&self) -> Vec<usize> {
+        let mut offsets = Vec::new();
+        let mut next = self.u32_at(4);
+
+        while let Some(offset) = next.filter(|offset| *offset != 0).map(|o| o as usize) {
+            if offsets.contains(&offset) || offsets.len() == MAX_TIFF_PAGES {
+                break;
+            }
+
+            let Some(entry_count) = self.u16_at(offset) else {
+                break;
+            };
+
+            offsets.push(offset);
+            next = self.u32_at(offset + 2 + entry_count as usize * 12);
+        }
+
+        offsets
+    }
+
+    fn page_info(&self, ifd: usize) -> Option<PageInfo> {
+        let entry_count = self.u16_at(ifd)? as usize;
+        let mut width = None;
+        let mut height = None;
+
+        for index in 0..entry_count {
+            let entry = ifd + 2 + index * 12;
+            let tag = self.u16_at(entry)?;
+            if tag != TIFF_IMAGE_WIDTH && tag != TIFF_IMAGE_LENGTH {
+                continue;
+            }
+
+            // The size is stored as a SHORT or a LONG
+            let value = match self.u16_at(entry + 2)? {
+                3 => self.u16_at(entry + 8)? as u32,
+                4 => self.u32_at(entry + 8)?,
+                _ => continue,
+            };
+
+            match tag {
+                TIFF_IMAGE_WIDTH => width = Some(value),
+                _ => height = Some(value),
+            }
+        }
+
+        Some(PageInfo {
+            width: width?,
+            height: height?,
+        })
+    }
+}
+
+struct IcoEntry {
+    width: u32,
+    height: u32,
+    bits_per_pixel: u16,
+    /// The 16 bytes of the entry in the directory.
+    raw: [u8; ICO_ENTRY_LEN],
+    data_offset: usize,
+    data_len: usize,
+}
+
+fn ico_entries(buffer: &[u8]) -> Option<Vec<IcoEntry>> {
+    let [0, 0, 1, 0, count_low, count_high] = *buffer.get(..ICO_HEADER_LEN)? else {
+        return None;
+    };
+
+    let count = u16::from_le_bytes([count_low, count_high]) as usize;
+    let entries = (0..count)
+        .map(|index| {
+            let start = ICO_HEADER_LEN + index * ICO_ENTRY_LEN;
+            let raw: [u8; ICO_ENTRY_LEN] =
+                buffer.get(start..start + ICO_ENTRY_LEN)?.try_into().ok()?;
+            let u32_at =
+                |i: usize| u32::from_le_bytes([raw[i], raw[i + 1], raw[i + 2], raw[i + 3]]);
+
+            // A size of 0 means 256 pixels
+            let size = |byte: u8| if byte == 0 { 256 } else { byte as u32 };
+
+            Some(IcoEntry {
+                width: size(raw[0]),
+                height: size(raw[1]),
+                bits_per_pixel: u16::from_le_bytes([raw[6], raw[7]]),
+                raw,
+                data_offset: u32_at(12) as usize,
+                data_len: u32_at(8) as usize,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    (!entries.is_empty()).then_some(entries)
+}
+
+fn largest_ico_entry(entries: &[IcoEntry]) -> usize {
+    entries
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, entry)| (entry.width * entry.height, entry.bits_per_pixel))
+        .map(|(index, _)| index)
+        .unwrap_or(0)
+}
+
+/// Builds an ICO file with a single entry.
+fn ico_with_entry(buffer: &[u8], entry: &IcoEntry) -> Result<Vec<u8>, Error> {
+    let data = buffer
+        .get(entry.data_offset..)
+        .and_then(|data| data.get(..entry.data_len))
+        .ok_or_else(|| ResponseError::new(StatusCode::BAD_REQUEST, "invalid ICO entry"))?;
+
+    let data_offset = (ICO_HEADER_LEN + ICO_ENTRY_LEN) as u32;
+    let mut ico = Vec::with_capacity(data_offset as usize + data.len());
+    ico.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+    ico.extend_from_slice(&entry.raw[..12]);
+    ico.extend_from_slice(&data_offset.to_le_bytes());
+    ico.extend_from_slice(data);
+    Ok(ico)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{
+        codecs::ico::{IcoEncoder, IcoFrame},
+        ColorType, GenericImageView,
+    };
+
+    /// An uncompressed 8-bit grayscale TIFF with a page for each size.
+    fn tiff(sizes: &[(u32, u32)], little_endian: bool) -> Vec<u8> {
+        let u16_bytes = |value: u16| match little_endian {
+            true => value.to_le_bytes(),
+            false => value.to_be_bytes(),
+        };
+        let u32_bytes = |value: u32| match little_endian {
+            true => value.to_le_bytes(),
+            false => value.to_be_bytes(),
+        };
+
+        let mut buf = match little_endian {
+            true => b"II*\0".to_vec(),
+            false => b"MM\0*".to_vec(),
+        };
+        // The offset of the next IFD is patched once it's written
+        let mut next_ifd_at = buf.len();
+        buf.extend_from_slice(&[0; 4]);
+
+        for (index, &(width, height)) in sizes.iter().enumerate() {
+            let strip_offset = buf.len() as u32;
+            buf.resize(buf.len() + (width * height) as usize, index as u8 * 100);
+            if buf.len() % 2 == 1 {
+                buf.push(0);
+            }
+
+            let ifd = buf.len() as u32;
+            buf[next_ifd_at..next_ifd_at + 4].copy_from_slice(&u32_bytes(ifd));
+
+            // Tag, type (3 SHORT, 4 LONG), count, value
+            let short = |value: u32| {
+                let mut bytes = u16_bytes(value as u16).to_vec();
+                bytes.extend_from_slice(&[0, 0]);
+                (3, bytes)
+            };
+            let long = |value: u32| (4, u32_bytes(value).to_vec());
+            let entries = [
+                (TIFF_IMAGE_WIDTH, short(width)),
+                (TIFF_IMAGE_LENGTH, long(height)),
+                (258, short(8)),
+                (259, short(1)),
+                (262, short(1)),
+                (273, long(strip_offset)),
+                (278, long(height)),
+                (279, long(width * height)),
+            ];
+
+            buf.extend_from_slice(&u16_bytes(entries.len() as u16));
+            for (tag, (kind, value)) in entries {
+                buf.extend_from_slice(&u16_bytes(tag));
+                buf.extend_from_slice(&u16_bytes(kind));
+                buf.extend_from_slice(&u32_bytes(1));
+                buf.extend_from_slice(&value);
+            }
+
+            next_ifd_at = buf.len();
+            buf.extend_from_slice(&[0; 4]);
+        }
+
+        buf
+    }
+
+    fn ico(sizes: &[u32]) -> Vec<u8> {
+        let frames = sizes
+            .iter()
+            .map(|&size| {
+                let pixels = vec![200; (size * size * 4) as usize];
+                IcoFrame::as_png(&pixels, size, size, ColorType::Rgba8).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let mut buf = Vec::new();
+        IcoEncoder::new(&mut buf).encode_images(&frames).unwrap();
+        buf
+    }
+
+    fn dimensions(buffer: &[u8], format: ImageFormat) -> (u32, u32) {
+        image::load_from_memory_with_format(buffer, format)
+            .unwrap()
+            .dimensions()
+    }
+
+    fn sizes(pages: Vec<PageInfo>) -> Vec<(u32, u32)> {
+        pages.iter().map(|page| (page.width, page.height)).collect()
+    }
+
+    fn status(err: Error) -> StatusCode {
+        ResponseError::from_boxed(err).status()
+    }
+
+    #[test]
+    fn reads_tiff_pages() {
+        for little_endian in [true, false] {
+            let tiff = tiff(&[(4, 3), (6, 5), (2, 7)], little_endian);
+            let pages = read_pages(&tiff, ImageFormat::Tiff).unwrap();
+
+            assert_eq!(sizes(pages), [(4, 3), (6, 5), (2, 7)]);
+        }
+    }
+
+    #[test]
+    fn extracts_tiff_pages() {
+        for little_endian in [true, false] {
+            let tiff = tiff(&[(4, 3), (6, 5), (2, 7)], little_endian);
+
+            assert_eq!(dimensions(&tiff, ImageFormat::Tiff), (4, 3));
+            assert!(extract_page(&tiff, ImageFormat::Tiff, None)
+                .unwrap()
+                .is_none());
+            assert!(extract_page(&tiff, ImageFormat::Tiff, Some(0))
+                .unwrap()
+                .is_none());
+
+            for (page, size) in [(1, (6, 5)), (2, (2, 7))] {
+                let page = extract_page(&tiff, ImageFormat::Tiff, Some(page))
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(dimensions(&page, ImageFormat::Tiff), size);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_missing_tiff_pages() {
+        let tiff = tiff(&[(4, 3), (6, 5)], true);
+        let err = extract_page(&tiff, ImageFormat::Tiff, Some(2)).unwrap_err();
+
+        assert_eq!(status(err), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn stops_at_circular_tiff_pages() {
+        let mut tiff = tiff(&[(4, 3), (6, 5)], true);
+        // The last IFD points back to the first one
+        let first_ifd = tiff[4..8].to_vec();
+        let len = tiff.len();
+        tiff[len - 4..].copy_from_slice(&first_ifd);
+
+        assert_eq!(read_pages(&tiff, ImageFormat::Tiff).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reads_ico_entries() {
+        let ico = ico(&[16, 48, 32]);
+        let pages = read_pages(&ico, ImageFormat::Ico).unwrap();
+
+        assert_eq!(sizes(pages), [(16, 16), (48, 48), (32, 32)]);
+    }
+
+    #[test]
+    fn extracts_the_largest_ico_entry_by_default() {
+        let ico = ico(&[16, 48, 32]);
+        let entry = extract_page(&ico, ImageFormat::Ico, None).unwrap().unwrap();
+
+        assert_eq!(dimensions(&entry, ImageFormat::Ico), (48, 48));
+    }
+
+    #[test]
+    fn extracts_ico_entries() {
+        let ico = ico(&[16, 48, 32]);
+
+        for (page, size) in [(0, 16), (1, 48), (2, 32)] {
+            let entry = extract_page(&ico, ImageFormat::Ico, Some(page))
+                .unwrap()
+                .unwrap();
+            assert_eq!(read_pages(&entry, ImageFormat::Ico).unwrap().len(), 1);
+            assert_eq!(dimensions(&entry, ImageFormat::Ico), (size, size));
+        }
+
+        let err = extract_page(&ico, ImageFormat::Ico, Some(3)).unwrap_err();
+        assert_eq!(status(err), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn rejects_pages_of_single_page_images() {
+        assert!(extract_page(b"GIF89a", ImageFormat::Gif, Some(0))
+            .unwrap()
+            .is_none());
+
+        let err = extract_page(b"GIF89a", ImageFormat::Gif, Some(1)).unwrap_err();
+        assert_eq!(status(err), StatusCode::BAD_REQUEST);
+    }
+}
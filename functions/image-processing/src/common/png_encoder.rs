@@ -0,0 +1,70 @@
+use super::{write_image, ImageHandlerOptions};
+use crate::error::ResponseError;
+use color_quant::NeuQuant;
+use image::{imageops, DynamicImage, ImageOutputFormat};
+use lambda_runtime::Error;
+
+/// Sampling factor of the quantizer, between 1 (slower but better) and 30.
+const QUANTIZER_SAMPLE_FACTOR: i32 = 10;
+
+/// `oxipng` optimization level, between 0 (fast) and 6 (slower but smaller).
+const OPTIMIZATION_LEVEL: u8 = 2;
+
+/// Encodes the image as PNG, optionally reducing it to a palette of `colors`
+/// and searching for the smallest filters and deflate compression.
+pub fn encode_png(img: &DynamicImage, options: &ImageHandlerOptions) -> Result<Vec<u8>, Error> {
+    let buf = match options.colors {
+        Some(colors) => quantize_png(img, colors, options.dither)?,
+        None => write_image(img, ImageOutputFormat::Png)?,
+    };
+
+    if !options.optimize {
+        return Ok(buf);
+    }
+
+    let opts = oxipng::Options::from_preset(OPTIMIZATION_LEVEL);
+    let optimized = oxipng::optimize_from_memory(&buf, &opts).map_err(ResponseError::from_error)?;
+    Ok(optimized)
+}
+
+/// Encodes the image as an indexed PNG with at most `colors` colors.
+fn quantize_png(img: &DynamicImage, colors: u16, dither: bool) -> Result<Vec<u8>, Error> {
+    let mut rgba = img.to_rgba8();
+    let quantizer = NeuQuant::new(QUANTIZER_SAMPLE_FACTOR, colors as usize, rgba.as_raw());
+
+    if dither {
+        imageops::dither(&mut rgba, &quantizer);
+    }
+
+    let indices = rgba
+        .pixels()
+        .map(|p| quantizer.index_of(&p.0) as u8)
+        .collect::<Vec<_>>();
+
+    let color_map = quantizer.color_map_rgba();
+    let palette = color_map
+        .chunks_exact(4)
+        .flat_map(|c| [c[0], c[1], c[2]])
+        .collect::<Vec<_>>();
+    let alphas = color_map.chunks_exact(4).map(|c| c[3]).collect::<Vec<_>>();
+
+    let mut buf = Vec::new();
+
+    {
+        let mut encoder = png::Encoder::new(&mut buf, rgba.width(), rgba.height());
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(palette);
+
+        if alphas.iter().any(|a| *a != u8::MAX) {
+            encoder.set_trns(alphas);
+        }
+
+        let mut writer = encoder.write_header().map_err(ResponseError::from_error)?;
+        writer
+            .write_image_data(&indices)
+            .map_err(ResponseError::from_error)?;
+    }
+
+    Ok(buf)
+}
@@ -0,0 +1,190 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView, Rgb};
+
+/// Max size of the longest side of the image used for the analysis.
+const ANALYSIS_SIZE: u32 = 256;
+
+/// Number of positions tested along each axis.
+const STEPS: u32 = 16;
+
+const EDGE_WEIGHT: f64 = 1.0;
+const SKIN_WEIGHT: f64 = 1.8;
+const SATURATION_WEIGHT: f64 = 0.3;
+const ENTROPY_WEIGHT: f64 = 0.4;
+const CENTER_WEIGHT: f64 = 0.1;
+
+/// Finds the position of the most interesting region of the given size in the image.
+///
+/// Each pixel is scored by its edge strength, how close it's to a skin tone and its saturation,
+/// then the windows are ranked by their mean score plus the entropy of their luminance.
+pub fn smart_crop_offset(img: &DynamicImage, crop_width: u32, crop_height: u32) -> (u32, u32) {
+    let (width, height) = img.dimensions();
+    if crop_width >= width && crop_height >= height {
+        return (0, 0);
+    }
+
+    let scale = (ANALYSIS_SIZE as f64 / width.max(height) as f64).min(1.0);
+    let small = if scale < 1.0 {
+        let w = ((width as f64 * scale).round() as u32).max(1);
+        let h = ((height as f64 * scale).round() as u32).max(1);
+        img.resize_exact(w, h, FilterType::Triangle).to_rgb8()
+    } else {
+        img.to_rgb8()
+    };
+
+    let (sw, sh) = small.dimensions();
+    let win_w = ((crop_width as f64 * scale).round() as u32).clamp(1, sw);
+    let win_h = ((crop_height as f64 * scale).round() as u32).clamp(1, sh);
+
+    let luma: Vec<f64> = small.pixels().map(luminance).collect();
+    let scores = score_map(&small, &luma);
+    let integral = IntegralImage::new(&scores, sw, sh);
+
+    let free_x = sw - win_w;
+    let free_y = sh - win_h;
+    let step_x = (free_x / STEPS).max(1);
+    let step_y = (free_y / STEPS).max(1);
+
+    let mut best = (0, 0);
+    let mut best_score = f64::MIN;
+
+    for y in (0..=free_y).step_by(step_y as usize) {
+        for x in (0..=free_x).step_by(step_x as usize) {
+            let area = (win_w * win_h) as f64;
+            let mean = integral.sum(x, y, win_w, win_h) / area;
+            let entropy = window_entropy(&luma, sw, x, y, win_w, win_h);
+
+            // Slightly prefer centered windows when the scores are similar
+            let cx = (x as f64 + win_w as f64 / 2.0) / sw as f64 - 0.5;
+            let cy = (y as f64 + win_h as f64 / 2.0) / sh as f64 - 0.5;
+            let center = 1.0 - (cx * cx + cy * cy).sqrt();
+
+            let score = mean + ENTROPY_WEIGHT * entropy + CENTER_WEIGHT * center;
+            if score > best_score {
+                best_score = score;
+                best = (x, y);
+            }
+        }
+    }
+
+    let x = ((best.0 as f64 / scale).round() as u32).min(width.saturating_sub(crop_width));
+    let y = ((best.1 as f64 / scale).round() as u32).min(height.saturating_sub(crop_height));
+    (x, y)
+}
+
+fn luminance(p: &Rgb<u8>) -> f64 {
+    let [r, g, b] = p.0;
+    (0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64) / 255.0
+}
+
+fn score_map(img: &image::RgbImage, luma: &[f64]) -> Vec<f64> {
+    let (w, h) = img.dimensions();
+    let mut scores = vec![0.0; (w * h) as usize];
+
+    for y in 0..h {
+        for x in 0..w {
+            let idx = (y * w + x) as usize;
+            let p = img.get_pixel(x, y);
+
+            let edge = sobel(luma, w, h, x, y);
+            let skin = skin_score(p);
+            let saturation = saturation(p);
+
+            scores[idx] = EDGE_WEIGHT * edge + SKIN_WEIGHT * skin + SATURATION_WEIGHT * saturation;
+        }
+    }
+
+    scores
+}
+
+fn sobel(luma: &[f64], w: u32, h: u32, x: u32, y: u32) -> f64 {
+    let at = |dx: i64, dy: i64| {
+        let px = (x as i64 + dx).clamp(0, w as i64 - 1);
+        let py = (y as i64 + dy).clamp(0, h as i64 - 1);
+        luma[(py * w as i64 + px) as usize]
+    };
+
+    let gx = -at(-1, -1) - 2.0 * at(-1, 0) - at(-1, 1) + at(1, -1) + 2.0 * at(1, 0) + at(1, 1);
+    let gy = -at(-1, -1) - 2.0 * at(0, -1) - at(1, -1) + at(-1, 1) + 2.0 * at(0, 1) + at(1, 1);
+    ((gx * gx + gy * gy).sqrt() / 4.0).min(1.0)
+}
+
+fn skin_score(p: &Rgb<u8>) -> f64 {
+    let [r, g, b] = p.0.map(|c| c as f64);
+    let mag = (r * r + g * g + b * b).sqrt();
+    if mag < 40.0 {
+        return 0.0;
+    }
+
+    // Distance to an average normalized skin color
+    let (sr, sg, sb) = (0.78, 0.57, 0.44);
+    let (dr, dg, db) = (r / mag - sr, g / mag - sg, b / mag - sb);
+    let distance = (dr * dr + dg * dg + db * db).sqrt();
+    (1.0 - distance / 0.25).max(0.0)
+}
+
+fn saturation(p: &Rgb<u8>) -> f64 {
+    let [r, g, b] = p.0.map(|c| c as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    if max == 0.0 {
+        0.0
+    } else {
+        (max - min) / max
+    }
+}
+
+/// Normalized Shannon entropy of the luminance of a window, between 0 and 1.
+fn window_entropy(luma: &[f64], stride: u32, x: u32, y: u32, w: u32, h: u32) -> f64 {
+    const BINS: usize = 32;
+    let mut histogram = [0u32; BINS];
+
+    for py in y..(y + h) {
+        for px in x..(x + w) {
+            let l = luma[(py * stride + px) as usize];
+            let bin = ((l * BINS as f64) as usize).min(BINS - 1);
+            histogram[bin] += 1;
+        }
+    }
+
+    let total = (w * h) as f64;
+    let entropy: f64 = histogram
+        .iter()
+        .filter(|&&n| n > 0)
+        .map(|&n| {
+            let p = n as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+
+    entropy / (BINS as f64).log2()
+}
+
+/// Summed area table to get the sum of any rectangle in constant time.
+struct IntegralImage {
+    data: Vec<f64>,
+    width: u32,
+}
+
+impl IntegralImage {
+    fn new(values: &[f64], width: u32, height: u32) -> Self {
+        let stride = width + 1;
+        let mut data = vec![0.0; (stride * (height + 1)) as usize];
+
+        for y in 0..height {
+            let mut row = 0.0;
+            for x in 0..width {
+                row += values[(y * width + x) as usize];
+                let above = data[(y * stride + x + 1) as usize];
+                data[((y + 1) * stride + x + 1) as usize] = above + row;
+            }
+        }
+
+        IntegralImage { data, width }
+    }
+
+    fn sum(&self, x: u32, y: u32, w: u32, h: u32) -> f64 {
+        let stride = self.width + 1;
+        let at = |x: u32, y: u32| self.data[(y * stride + x) as usize];
+        at(x + w, y + h) - at(x, y + h) - at(x + w, y) + at(x, y)
+    }
+}
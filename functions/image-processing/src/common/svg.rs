@@ -0,0 +1,76 @@
+use super::validation::MAX_WIDTH;
+use crate::error::ResponseError;
+use image::{DynamicImage, RgbaImage};
+use lambda_runtime::Error;
+use once_cell::sync::Lazy;
+use reqwest::StatusCode;
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{fontdb::Database, ImageHrefResolver, Options, Tree},
+};
+use std::sync::Arc;
+
+// Loading the system fonts is slow, so they are shared by all the requests
+static FONTS: Lazy<Arc<Database>> = Lazy::new(|| {
+    let mut fonts = Database::new();
+    fonts.load_system_fonts();
+    Arc::new(fonts)
+});
+
+/// Parses an SVG (or gzip compressed SVGZ) document.
+///
+/// Only images embedded as data URLs are loaded,
+/// references to local files or URLs are ignored.
+pub fn load_svg(buffer: &[u8]) -> Result<Tree, Error> {
+    let options = Options {
+        resources_dir: None,
+        fontdb: FONTS.clone(),
+        image_href_resolver: ImageHrefResolver {
+            resolve_data: ImageHrefResolver::default_data_resolver(),
+            resolve_string: Box::new(|href, _| {
+                tracing::warn!("ignoring external SVG resource: {href}");
+                None
+            }),
+        },
+        ..Options::default()
+    };
+
+    Tree::from_data(buffer, &options).map_err(|e| {
+        ResponseError::new(StatusCode::BAD_REQUEST, format!("invalid SVG image: {e}")).into()
+    })
+}
+
+/// Renders the SVG with the given width keeping the aspect ratio, or with its own size.
+pub fn render_svg(tree: &Tree, width: Option<u32>) -> Result<DynamicImage, Error> {
+    let size = tree.size();
+    let scale = width.map_or(1.0, |width| width as f32 / size.width());
+    let width = (size.width() * scale).round().max(1.0) as u32;
+    let height = (size.height() * scale).round().max(1.0) as u32;
+
+    if width > MAX_WIDTH || height > MAX_WIDTH {
+        return Err(ResponseError::new(
+            StatusCode::BAD_REQUEST,
+            format!("the SVG size {width}x{height} exceeds the max of {MAX_WIDTH}x{MAX_WIDTH}"),
+        )
+        .into());
+    }
+
+    let mut pixmap = Pixmap::new(width, height)
+        .ok_or_else(|| Error::from(format!("failed to allocate a {width}x{height} image")))?;
+    resvg::render(tree, Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    // The pixmap uses premultiplied alpha
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+
+    let img = RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| Error::from("failed to read the rendered SVG"))?;
+
+    Ok(DynamicImage::ImageRgba8(img))
+}
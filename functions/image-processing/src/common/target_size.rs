@@ -0,0 +1,96 @@
+use super::{
+    embed_metadata, encode_image, output_format, ImageByteBuffer, ImageHandlerOptions, Metadata,
+    OutputFormat,
+};
+use crate::error::ResponseError;
+use image::{imageops::FilterType, DynamicImage};
+use lambda_runtime::Error;
+use reqwest::StatusCode;
+use std::borrow::Cow;
+
+/// Scale applied to the image each time it doesn't fit with the minimum quality.
+const DOWNSCALE_FACTOR: f64 = 0.75;
+
+/// Smallest size of the longest side the image can be downscaled to.
+const MIN_DOWNSCALE_SIZE: u32 = 16;
+
+/// Encodes the image with the highest quality that fits in `max_bytes`, using a binary search.
+/// The size includes the metadata, which is embedded in each attempt.
+///
+/// If `max_bytes_downscale` is set and the image doesn't fit even with the lowest quality,
+/// it's downscaled until it fits.
+pub fn encode_with_max_bytes(
+    img: &DynamicImage,
+    options: &ImageHandlerOptions,
+    max_bytes: usize,
+    metadata: &Metadata,
+) -> Result<ImageByteBuffer, Error> {
+    let mut img = Cow::Borrowed(img);
+
+    loop {
+        if let Some(encoded) = encode_max_quality(&img, options, max_bytes, metadata)? {
+            return Ok(encoded);
+        }
+
+        let (width, height) = (img.width(), img.height());
+        if !options.max_bytes_downscale || width.max(height) <= MIN_DOWNSCALE_SIZE {
+            return Err(ResponseError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("unable to encode the image in {max_bytes} bytes"),
+            )
+            .into());
+        }
+
+        let new_width = ((width as f64 * DOWNSCALE_FACTOR) as u32).max(1);
+        let new_height = ((height as f64 * DOWNSCALE_FACTOR) as u32).max(1);
+        tracing::info!("downscaling image to {new_width}x{new_height} to fit in {max_bytes} bytes");
+        img = Cow::Owned(img.resize_exact(new_width, new_height, FilterType::Lanczos3));
+    }
+}
+
+fn encode_max_quality(
+    img: &DynamicImage,
+    options: &ImageHandlerOptions,
+    max_bytes: usize,
+    metadata: &Metadata,
+) -> Result<Option<ImageByteBuffer>, Error> {
+    let encode = |options: &ImageHandlerOptions| -> Result<ImageByteBuffer, Error> {
+        let mut encoded = encode_image(img, options)?;
+        encoded.buf = embed_metadata(encoded.buf, encoded.format, metadata)?;
+        Ok(encoded)
+    };
+
+    let has_quality = match output_format(options) {
+        OutputFormat::Jpeg | OutputFormat::Avif => true,
+        OutputFormat::WebP => !options.lossless && options.near_lossless.is_none(),
+        OutputFormat::Png | OutputFormat::Gif => false,
+    };
+
+    if !has_quality {
+        let encoded = encode(options)?;
+        return Ok(Some(encoded).filter(|e| e.buf.len() <= max_bytes));
+    }
+
+    let mut options = options.clone();
+    let mut low = 1;
+    let mut high = options.quality.unwrap_or(100);
+    let mut best = None;
+
+    while low <= high {
+        let quality = low + (high - low) / 2;
+        options.quality = Some(quality);
+
+        let mut encoded = encode(&options)?;
+        if encoded.buf.len() <= max_bytes {
+            encoded.quality = Some(quality);
+            best = Some(encoded);
+            low = quality + 1;
+        } else if quality == 1 {
+            break;
+        } else {
+            high = quality - 1;
+        }
+    }
+
+    Ok(best)
+}
@@ -0,0 +1,52 @@
+use super::CropRect;
+use image::{DynamicImage, GenericImageView, Rgba};
+
+pub const DEFAULT_TRIM_THRESHOLD: u8 = 10;
+
+/// Returns the bounding box of the pixels that differ from the top-left corner color
+/// by more than `threshold` in any channel, expanded by `padding` pixels on each side.
+///
+/// Returns `None` if the whole image has the corner color.
+pub fn trim_bounds(img: &DynamicImage, threshold: u8, padding: u32) -> Option<CropRect> {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let corner = img.get_pixel(0, 0);
+    let differs = |p: Rgba<u8>| {
+        p.0.iter()
+            .zip(corner.0.iter())
+            .any(|(a, b)| a.abs_diff(*b) > threshold)
+    };
+
+    let mut min_x = u32::MAX;
+    let mut min_y = u32::MAX;
+    let mut max_x = 0;
+    let mut max_y = 0;
+
+    for (x, y, pixel) in img.pixels() {
+        if differs(pixel) {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+
+    if min_x == u32::MAX {
+        return None;
+    }
+
+    let crop_x = min_x.saturating_sub(padding);
+    let crop_y = min_y.saturating_sub(padding);
+    let right = max_x.saturating_add(padding).min(width - 1);
+    let bottom = max_y.saturating_add(padding).min(height - 1);
+
+    Some(CropRect {
+        crop_x,
+        crop_y,
+        crop_width: right - crop_x + 1,
+        crop_height: bottom - crop_y + 1,
+    })
+}
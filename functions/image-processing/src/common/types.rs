@@ -1,24 +1,467 @@
-use serde_aux::prelude::*;
-use serde::Deserialize;
+use image::Rgba;
+use serde::{de, Deserialize, Deserializer};
+use std::{fmt, str::FromStr};
 
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum FlipImage {
     Vertical,
     Horizontal,
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct CropRect {
-    #[serde(deserialize_with = "deserialize_number_from_string")]
     pub crop_x: u32,
-
-    #[serde(deserialize_with = "deserialize_number_from_string")]
     pub crop_y: u32,
-
-    #[serde(deserialize_with = "deserialize_number_from_string")]
     pub crop_width: u32,
-
-    #[serde(deserialize_with = "deserialize_number_from_string")]
     pub crop_height: u32,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[serde(alias = "jpg")]
+    Jpeg,
+    Png,
+    Gif,
+    WebP,
+    /// Requires the `avif` feature.
+    Avif,
+}
+
+/// The icons generated from the image instead of a single output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IconSet {
+    /// A `favicon.ico` with the 16, 32, 48, 64 and 256 pixels sizes
+    Favicon,
+    /// A ZIP with the favicon, PNG icons for browsers, iOS and Android and a `manifest.json` snippet
+    App,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim().to_ascii_lowercase();
+        OutputFormat::deserialize(de::value::StrDeserializer::<de::value::Error>::new(&value))
+            .map_err(|_| {
+                format!("invalid format `{s}`, expected `jpeg`, `png`, `gif`, `webp` or `avif`")
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ChromaSubsampling {
+    #[serde(rename = "444")]
+    Yuv444,
+    #[serde(rename = "422")]
+    Yuv422,
+    #[serde(rename = "420")]
+    Yuv420,
+}
+
+/// How the embedded ICC profile of the source image is handled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorProfile {
+    /// Convert the pixels to sRGB before processing
+    #[default]
+    Srgb,
+    /// Keep the pixels as they are and embed the profile in the output
+    Preserve,
+}
+
+/// The metadata of the source image kept in the output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataMode {
+    Strip,
+    /// ICC profile, EXIF and XMP
+    Keep,
+    /// Only the ICC profile, if it's preserved with `ColorProfile::Preserve`
+    #[default]
+    Icc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mask {
+    Circle,
+    Ellipse,
+}
+
+/// Where to place a region inside a bigger area.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Gravity {
+    #[default]
+    Center,
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+    /// Content-aware position, selects the most interesting region of the image.
+    #[serde(alias = "attention")]
+    Smart,
+}
+
+impl Gravity {
+    /// Returns the offset of a region of `size` inside an area of `total`,
+    /// `Smart` is centered because it requires the image to be resolved.
+    ///
+    /// The offset is negative if the region is bigger than the area.
+    pub fn offset(&self, total: (u32, u32), size: (u32, u32)) -> (i64, i64) {
+        let free_x = total.0 as i64 - size.0 as i64;
+        let free_y = total.1 as i64 - size.1 as i64;
+
+        let x = match self {
+            Gravity::West | Gravity::NorthWest | Gravity::SouthWest => 0,
+            Gravity::East | Gravity::NorthEast | Gravity::SouthEast => free_x,
+            Gravity::Center | Gravity::North | Gravity::South | Gravity::Smart => free_x / 2,
+        };
+
+        let y = match self {
+            Gravity::North | Gravity::NorthWest | Gravity::NorthEast => 0,
+            Gravity::South | Gravity::SouthWest | Gravity::SouthEast => free_y,
+            Gravity::Center | Gravity::West | Gravity::East | Gravity::Smart => free_y / 2,
+        };
+
+        (x, y)
+    }
+}
+
+/// A length in pixels or as a percentage of the image size, `120` or `50%`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    Pixels(u32),
+    Percent(f32),
+}
+
+impl Dimension {
+    pub fn resolve(&self, total: u32) -> u32 {
+        match *self {
+            Dimension::Pixels(px) => px,
+            Dimension::Percent(p) => ((p as f64 / 100.0) * total as f64).round() as u32,
+        }
+    }
+}
+
+impl FromStr for Dimension {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Some(percent) = s.strip_suffix('%') {
+            let value = percent
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| format!("invalid percentage `{s}`"))?;
+
+            if !(0.0..=100.0).contains(&value) {
+                return Err(format!(
+                    "percentage must be between 0% and 100%, but was `{s}`"
+                ));
+            }
+
+            return Ok(Dimension::Percent(value));
+        }
+
+        s.parse::<u32>()
+            .map(Dimension::Pixels)
+            .map_err(|_| format!("invalid dimension `{s}`, expected pixels or a percentage"))
+    }
+}
+
+impl<'de> Deserialize<'de> for Dimension {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DimensionVisitor;
+
+        impl<'de> de::Visitor<'de> for DimensionVisitor {
+            type Value = Dimension;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number of pixels or a percentage")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                u32::try_from(v).map(Dimension::Pixels).map_err(E::custom)
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                u32::try_from(v).map(Dimension::Pixels).map_err(E::custom)
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(DimensionVisitor)
+    }
+}
+
+/// An aspect ratio in the form `16:9`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AspectRatio {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AspectRatio {
+    /// Returns the biggest size with this aspect ratio that fits in the given size.
+    pub fn fit(&self, width: u32, height: u32) -> (u32, u32) {
+        let w = width as u64 * self.height as u64;
+        let h = height as u64 * self.width as u64;
+
+        if w > h {
+            let new_width = (height as u64 * self.width as u64) / self.height as u64;
+            (new_width as u32, height)
+        } else {
+            let new_height = (width as u64 * self.height as u64) / self.width as u64;
+            (width, new_height as u32)
+        }
+    }
+}
+
+impl FromStr for AspectRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid aspect ratio `{s}`, expected `width:height` like `16:9`");
+        let (w, h) = s.trim().split_once(':').ok_or_else(err)?;
+        let width = w.trim().parse::<u32>().map_err(|_| err())?;
+        let height = h.trim().parse::<u32>().map_err(|_| err())?;
+
+        if width == 0 || height == 0 {
+            return Err(err());
+        }
+
+        Ok(AspectRatio { width, height })
+    }
+}
+
+impl<'de> Deserialize<'de> for AspectRatio {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// A RGBA color parsed from `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa` (the `#` is optional),
+/// `rgb(r, g, b)`, `rgba(r, g, b, a)` or `transparent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color(pub Rgba<u8>);
+
+impl Color {
+    pub const WHITE: Color = Color(Rgba([255, 255, 255, 255]));
+    pub const BLACK: Color = Color(Rgba([0, 0, 0, 255]));
+    pub const TRANSPARENT: Color = Color(Rgba([0, 0, 0, 0]));
+
+    pub fn is_opaque(&self) -> bool {
+        self.0[3] == u8::MAX
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err =
+            || format!("invalid color `{s}`, expected hex, `rgb(r, g, b)` or `rgba(r, g, b, a)`");
+        let value = s.trim().to_ascii_lowercase();
+
+        if value == "transparent" {
+            return Ok(Color::TRANSPARENT);
+        }
+
+        if let Some(args) = value
+            .strip_prefix("rgba(")
+            .or_else(|| value.strip_prefix("rgb("))
+            .and_then(|v| v.strip_suffix(')'))
+        {
+            let parts = args.split(',').map(str::trim).collect::<Vec<_>>();
+            let channel = |v: &str| v.parse::<u8>().map_err(|_| err());
+
+            return match parts.as_slice() {
+                [r, g, b] => Ok(Color(Rgba([channel(r)?, channel(g)?, channel(b)?, 255]))),
+                [r, g, b, a] => {
+                    // Alpha is a fraction as in CSS
+                    let a = a.parse::<f32>().map_err(|_| err())?;
+                    if !(0.0..=1.0).contains(&a) {
+                        return Err(err());
+                    }
+
+                    let a = (a * 255.0).round() as u8;
+                    Ok(Color(Rgba([channel(r)?, channel(g)?, channel(b)?, a])))
+                }
+                _ => Err(err()),
+            };
+        }
+
+        let hex = value.strip_prefix('#').unwrap_or(&value);
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(err());
+        }
+
+        let digit = |i: usize| u8::from_str_radix(&hex[i..=i], 16).map(|v| v * 17);
+        let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+
+        let rgba = match hex.len() {
+            3 => [digit(0), digit(1), digit(2), Ok(255)],
+            4 => [digit(0), digit(1), digit(2), digit(3)],
+            6 => [byte(0), byte(2), byte(4), Ok(255)],
+            8 => [byte(0), byte(2), byte(4), byte(6)],
+            _ => return Err(err()),
+        };
+
+        let [r, g, b, a] = rgba.map(|c| c.map_err(|_| err()));
+        Ok(Color(Rgba([r?, g?, b?, a?])))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Sizes for each side of an image, in the CSS order: `all`, `vertical,horizontal`,
+/// `top,horizontal,bottom` or `top,right,bottom,left`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Sides {
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+    pub left: u32,
+}
+
+impl Sides {
+    pub fn horizontal(&self) -> u32 {
+        self.left.saturating_add(self.right)
+    }
+
+    pub fn vertical(&self) -> u32 {
+        self.top.saturating_add(self.bottom)
+    }
+}
+
+impl FromStr for Sides {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid sides `{s}`, expected 1 to 4 comma separated values");
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<u32>().map_err(|_| err()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (top, right, bottom, left) = match values.as_slice() {
+            [all] => (*all, *all, *all, *all),
+            [v, h] => (*v, *h, *v, *h),
+            [t, h, b] => (*t, *h, *b, *h),
+            [t, r, b, l] => (*t, *r, *b, *l),
+            _ => return Err(err()),
+        };
+
+        Ok(Sides {
+            top,
+            right,
+            bottom,
+            left,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Sides {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = serde_aux::prelude::deserialize_string_from_number(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// A size in pixels in the form `400x300`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid size `{s}`, expected `widthxheight` like `400x300`");
+        let (w, h) = s.trim().split_once(['x', 'X']).ok_or_else(err)?;
+        let width = w.trim().parse::<u32>().map_err(|_| err())?;
+        let height = h.trim().parse::<u32>().map_err(|_| err())?;
+        Ok(Size { width, height })
+    }
+}
+
+impl<'de> Deserialize<'de> for Size {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// A comma separated list of values like `320,640,1280`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueList<T>(pub Vec<T>);
+
+impl<T> FromStr for ValueList<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| {
+                v.trim()
+                    .parse::<T>()
+                    .map_err(|e| format!("invalid list `{s}`: {e}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ValueList(values))
+    }
+}
+
+impl<'de, T> Deserialize<'de> for ValueList<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = serde_aux::prelude::deserialize_string_from_number(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
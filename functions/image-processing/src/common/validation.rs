@@ -0,0 +1,332 @@
+use super::{
+    output_format, resized_height, ColorProfile, Gravity, ImageHandlerOptions, MetadataMode,
+    OutputFormat,
+};
+use crate::error::ResponseError;
+use reqwest::StatusCode;
+
+pub const MAX_WIDTH: u32 = 10_000;
+pub const MIN_QUALITY: u8 = 1;
+pub const MAX_QUALITY: u8 = 100;
+pub const MAX_BLUR: f32 = 100.0;
+pub const MAX_SHARP: f32 = 100.0;
+pub const MAX_CONTRAST: f32 = 100.0;
+pub const MAX_BRIGHTNESS: i32 = 255;
+pub const MAX_HUE: i32 = 360;
+pub const MIN_SPEED: u8 = 1;
+pub const MAX_SPEED: u8 = 10;
+pub const MIN_COLORS: u16 = 2;
+pub const MAX_COLORS: u16 = 256;
+pub const MAX_OUTPUTS: usize = 20;
+
+/// Collects every invalid option instead of stopping at the first one.
+#[derive(Debug, Default)]
+pub struct Violations(Vec<String>);
+
+impl Violations {
+    pub fn push(&mut self, msg: impl Into<String>) {
+        self.0.push(msg.into());
+    }
+
+    pub fn check(&mut self, condition: bool, msg: impl FnOnce() -> String) {
+        if !condition {
+            self.0.push(msg());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), ResponseError> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+
+        Err(self.into_error())
+    }
+
+    pub fn into_error(self) -> ResponseError {
+        ResponseError::new(StatusCode::BAD_REQUEST, "invalid image options").with_errors(self.0)
+    }
+}
+
+/// Checks all the options against the dimensions of the source image,
+/// returning a single error with all the violations found.
+pub fn validate_options(
+    options: &ImageHandlerOptions,
+    img_width: u32,
+    img_height: u32,
+) -> Result<(), ResponseError> {
+    let mut violations = Violations::default();
+
+    // The crop is applied after resizing, so it's checked against the resized dimensions
+    let (mut width, mut height) = (img_width, img_height);
+
+    if let Some(w) = options.width {
+        violations.check(w > 0 && w <= MAX_WIDTH, || {
+            format!("`width` must be between 1 and {MAX_WIDTH}, but was {w}")
+        });
+
+        if w > 0 && img_width > 0 {
+            height = resized_height(w, img_width, img_height);
+            width = w;
+
+            violations.check(height <= MAX_WIDTH, || {
+                format!(
+                    "`width` {w} would resize the image to a height of {height}, \
+                     but the maximum is {MAX_WIDTH}"
+                )
+            });
+        }
+    }
+
+    if let Some(quality) = options.quality {
+        violations.check((MIN_QUALITY..=MAX_QUALITY).contains(&quality), || {
+            format!("`quality` must be between {MIN_QUALITY} and {MAX_QUALITY}, but was {quality}")
+        });
+    }
+
+    if let Some(blur) = options.blur {
+        violations.check(blur.is_finite() && (0.0..=MAX_BLUR).contains(&blur), || {
+            format!("`blur` must be between 0 and {MAX_BLUR}, but was {blur}")
+        });
+    }
+
+    if let Some(sharp) = options.sharp {
+        violations.check(
+            sharp.is_finite() && (0.0..=MAX_SHARP).contains(&sharp),
+            || format!("`sharp` must be between 0 and {MAX_SHARP}, but was {sharp}"),
+        );
+    }
+
+    if let Some(contrast) = options.contrast {
+        violations.check(
+            contrast.is_finite() && (-MAX_CONTRAST..=MAX_CONTRAST).contains(&contrast),
+            || format!("`contrast` must be between -{MAX_CONTRAST} and {MAX_CONTRAST}, but was {contrast}"),
+        );
+    }
+
+    if let Some(brightness) = options.brightness {
+        violations.check((-MAX_BRIGHTNESS..=MAX_BRIGHTNESS).contains(&brightness), || {
+            format!("`brightness` must be between -{MAX_BRIGHTNESS} and {MAX_BRIGHTNESS}, but was {brightness}")
+        });
+    }
+
+    if let Some(hue) = options.hue {
+        violations.check((-MAX_HUE..=MAX_HUE).contains(&hue), || {
+            format!("`hue` must be between -{MAX_HUE} and {MAX_HUE}, but was {hue}")
+        });
+    }
+
+    // The size of the image before `extent`, `pad` and `border` are applied
+    let mut output_size = (width as u64, height as u64);
+
+    match options.crop.resolve(width, height) {
+        Ok(Some(crop)) => {
+            output_size = (crop.crop_width as u64, crop.crop_height as u64);
+            violations.check(crop.crop_width > 0 && crop.crop_height > 0, || {
+                "crop width and height must be greater than 0".to_owned()
+            });
+
+            let right = crop.crop_x as u64 + crop.crop_width as u64;
+            let bottom = crop.crop_y as u64 + crop.crop_height as u64;
+
+            violations.check(right <= width as u64 && bottom <= height as u64, || {
+                format!(
+                    "crop area ({}, {}, {}x{}) is outside of the image bounds ({width}x{height})",
+                    crop.crop_x, crop.crop_y, crop.crop_width, crop.crop_height
+                )
+            });
+        }
+        Ok(None) => {}
+        Err(msg) => violations.push(msg),
+    }
+
+    // With `extent` the gravity positions the image, so it's only ambiguous without it
+    if options.crop.gravity.is_some() && options.crop.has_position() {
+        violations.check(options.extent.is_some(), || {
+            "`gravity` cannot be used with `crop_x` or `crop_y`".to_owned()
+        });
+    }
+
+    if options.crop.gravity == Some(Gravity::Smart) {
+        violations.check(options.extent.is_none(), || {
+            "`gravity=smart` cannot be used with `extent`, use one of the directions to position the image"
+                .to_owned()
+        });
+    }
+
+    if options.trim_threshold.is_some() || options.trim_padding.is_some() {
+        violations.check(options.trim, || {
+            "`trim_threshold` and `trim_padding` require `trim=true`".to_owned()
+        });
+    }
+
+    if let Some(extent) = options.extent {
+        violations.check(
+            (1..=MAX_WIDTH).contains(&extent.width) && (1..=MAX_WIDTH).contains(&extent.height),
+            || format!("`extent` width and height must be between 1 and {MAX_WIDTH}"),
+        );
+    }
+
+    for (name, sides) in [("pad", options.pad), ("border", options.border)] {
+        if let Some(sides) = sides {
+            violations.check(
+                sides.horizontal() <= MAX_WIDTH && sides.vertical() <= MAX_WIDTH,
+                || format!("`{name}` cannot add more than {MAX_WIDTH} pixels per axis"),
+            );
+        }
+    }
+
+    // Each option is limited but together they could still allocate a huge canvas
+    if options.extent.is_some() || options.pad.is_some() || options.border.is_some() {
+        if let Some(extent) = options.extent {
+            output_size = (extent.width as u64, extent.height as u64);
+        }
+
+        for sides in [options.pad, options.border].into_iter().flatten() {
+            output_size.0 += sides.horizontal() as u64;
+            output_size.1 += sides.vertical() as u64;
+        }
+
+        let (output_width, output_height) = output_size;
+        violations.check(
+            output_width <= MAX_WIDTH as u64 && output_height <= MAX_WIDTH as u64,
+            || {
+                format!(
+                    "the image with `extent`, `pad` and `border` would be {output_width}x{output_height}, \
+                     but the maximum is {MAX_WIDTH}x{MAX_WIDTH}"
+                )
+            },
+        );
+    }
+
+    violations.check(
+        options.border_color.is_none() || options.border.is_some(),
+        || "`border_color` requires `border`".to_owned(),
+    );
+
+    if let Some(speed) = options.speed {
+        violations.check((MIN_SPEED..=MAX_SPEED).contains(&speed), || {
+            format!("`speed` must be between {MIN_SPEED} and {MAX_SPEED}, but was {speed}")
+        });
+
+        violations.check(options.format == Some(OutputFormat::Avif), || {
+            "`speed` requires `format=avif`".to_owned()
+        });
+    }
+
+    for (name, value) in [
+        ("near_lossless", options.near_lossless),
+        ("alpha_quality", options.alpha_quality),
+    ] {
+        if let Some(value) = value {
+            violations.check(value <= 100, || {
+                format!("`{name}` must be between 0 and 100, but was {value}")
+            });
+        }
+    }
+
+    let webp_only =
+        options.lossless || options.near_lossless.is_some() || options.alpha_quality.is_some();
+    violations.check(
+        !webp_only || options.format == Some(OutputFormat::WebP),
+        || "`lossless`, `near_lossless` and `alpha_quality` require `format=webp`".to_owned(),
+    );
+
+    violations.check(
+        !(options.progressive || options.subsampling.is_some())
+            || output_format(options) == OutputFormat::Jpeg,
+        || "`progressive` and `subsampling` require JPEG output".to_owned(),
+    );
+
+    if let Some(colors) = options.colors {
+        violations.check((MIN_COLORS..=MAX_COLORS).contains(&colors), || {
+            format!("`colors` must be between {MIN_COLORS} and {MAX_COLORS}, but was {colors}")
+        });
+    }
+
+    violations.check(options.colors.is_some() || !options.dither, || {
+        "`dither` requires `colors`".to_owned()
+    });
+
+    violations.check(
+        !(options.optimize || options.colors.is_some())
+            || output_format(options) == OutputFormat::Png,
+        || "`optimize` and `colors` require PNG output".to_owned(),
+    );
+
+    if let Some(max_bytes) = options.max_bytes {
+        violations.check(max_bytes > 0, || {
+            "`max_bytes` must be greater than 0".to_owned()
+        });
+    }
+
+    violations.check(
+        options.max_bytes.is_some() || !options.max_bytes_downscale,
+        || "`max_bytes_downscale` requires `max_bytes`".to_owned(),
+    );
+
+    violations.check(
+        !options.keep_gps || options.metadata == Some(MetadataMode::Keep),
+        || "`keep_gps` requires `metadata=keep`".to_owned(),
+    );
+
+    violations.check(
+        options.color_profile != Some(ColorProfile::Preserve)
+            || options.metadata != Some(MetadataMode::Strip),
+        || "`color_profile=preserve` can't be used with `metadata=strip`".to_owned(),
+    );
+
+    violations.check(
+        options.icons.is_none()
+            || (options.width.is_none() && options.format.is_none() && options.max_bytes.is_none()),
+        || "`width`, `format` and `max_bytes` can't be used with `icons`".to_owned(),
+    );
+
+    violations.check(
+        options.icons.is_none()
+            || (options.color_profile != Some(ColorProfile::Preserve)
+                && options.metadata != Some(MetadataMode::Keep)),
+        || "`color_profile=preserve` and `metadata=keep` can't be used with `icons`".to_owned(),
+    );
+
+    violations.into_result()
+}
+
+/// Checks the options that produce several outputs, `widths` and `formats`,
+/// each combination of them is a separate output.
+pub fn validate_outputs(options: &ImageHandlerOptions) -> Result<(), ResponseError> {
+    let mut violations = Violations::default();
+    let widths = options.widths.as_ref().map_or(&[][..], |w| &w.0);
+    let formats = options.formats.as_ref().map_or(&[][..], |f| &f.0);
+
+    for w in widths {
+        violations.check(*w > 0 && *w <= MAX_WIDTH, || {
+            format!("`widths` must be between 1 and {MAX_WIDTH}, but was {w}")
+        });
+    }
+
+    let outputs = widths.len().max(1) * formats.len().max(1);
+    violations.check(outputs <= MAX_OUTPUTS, || {
+        format!(
+            "`widths` and `formats` can produce at most {MAX_OUTPUTS} outputs, but were {outputs}"
+        )
+    });
+
+    violations.check(options.width.is_none() || widths.is_empty(), || {
+        "`width` and `widths` can't be used together".to_owned()
+    });
+
+    violations.check(options.format.is_none() || formats.is_empty(), || {
+        "`format` and `formats` can't be used together".to_owned()
+    });
+
+    violations.check(
+        options.icons.is_none() || (widths.is_empty() && formats.is_empty()),
+        || "`widths` and `formats` can't be used with `icons`".to_owned(),
+    );
+
+    violations.into_result()
+}
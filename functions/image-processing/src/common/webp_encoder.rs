@@ -0,0 +1,90 @@
+use super::ImageHandlerOptions;
+use crate::error::ResponseError;
+use image::{codecs::gif::Repeat, DynamicImage, Frame};
+use lambda_runtime::Error;
+use webp::{AnimEncoder, AnimFrame, Encoder, WebPConfig};
+
+const DEFAULT_WEBP_QUALITY: u8 = 80;
+
+/// Method used by the encoder, between 0 (fast) and 6 (slower but smaller).
+const WEBP_METHOD: i32 = 4;
+
+fn webp_config(options: &ImageHandlerOptions) -> Result<WebPConfig, Error> {
+    let mut config = WebPConfig::new()
+        .map_err(|_| ResponseError::from_error("failed to initialize the webp encoder"))?;
+
+    let quality = options.quality.unwrap_or(DEFAULT_WEBP_QUALITY);
+    config.quality = quality as f32;
+    config.method = WEBP_METHOD;
+
+    // Near lossless preprocessing is only used by the lossless encoder
+    config.lossless = (options.lossless || options.near_lossless.is_some()) as i32;
+    if let Some(near_lossless) = options.near_lossless {
+        config.near_lossless = near_lossless as i32;
+    }
+
+    if let Some(alpha_quality) = options.alpha_quality {
+        config.alpha_quality = alpha_quality as i32;
+    }
+
+    Ok(config)
+}
+
+/// Encodes the image as a lossy or lossless WebP.
+pub fn encode_webp(img: &DynamicImage, options: &ImageHandlerOptions) -> Result<Vec<u8>, Error> {
+    let config = webp_config(options)?;
+    let rgba = img.to_rgba8();
+
+    let encoded = Encoder::from_rgba(rgba.as_raw(), rgba.width(), rgba.height())
+        .encode_advanced(&config)
+        .map_err(|e| ResponseError::from_error(format!("failed to encode webp: {e:?}")))?;
+
+    Ok(encoded.to_vec())
+}
+
+/// Encodes the frames as an animated WebP.
+pub fn encode_animated_webp(
+    frames: Vec<Frame>,
+    repeat: Option<Repeat>,
+    options: &ImageHandlerOptions,
+) -> Result<Vec<u8>, Error> {
+    let config = webp_config(options)?;
+    let (width, height) = match frames.first() {
+        Some(frame) => frame.buffer().dimensions(),
+        None => return Err(ResponseError::from_error("animation without frames").into()),
+    };
+
+    // Unlike GIF the WebP loop count includes the first play
+    let loop_count = match repeat {
+        None => 1,
+        Some(Repeat::Infinite) => 0,
+        Some(Repeat::Finite(n)) => n as i32 + 1,
+    };
+
+    let mut timestamps = Vec::with_capacity(frames.len());
+    let mut elapsed_ms = 0;
+    for frame in &frames {
+        timestamps.push(elapsed_ms);
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        elapsed_ms += (numer / denom.max(1)) as i32;
+    }
+
+    let mut encoder = AnimEncoder::new(width, height, &config);
+    encoder.set_loop_count(loop_count);
+
+    for (frame, timestamp) in frames.iter().zip(timestamps) {
+        let buffer = frame.buffer();
+        encoder.add_frame(AnimFrame::from_rgba(
+            buffer.as_raw(),
+            buffer.width(),
+            buffer.height(),
+            timestamp,
+        ));
+    }
+
+    let encoded = encoder
+        .try_encode()
+        .map_err(|e| ResponseError::from_error(format!("failed to encode webp: {e:?}")))?;
+
+    Ok(encoded.to_vec())
+}
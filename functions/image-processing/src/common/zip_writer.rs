@@ -0,0 +1,280 @@
+use flate2::{write::DeflateEncoder, Compression};
+use std::io::{self, Write};
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+
+/// Version 2.0, the first with deflate and directories.
+const ZIP_VERSION: u16 = 20;
+
+/// The file names are encoded as UTF-8.
+const UTF8_FLAG: u16 = 1 << 11;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// 1980-01-01, the earliest date in the MS-DOS format.
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+struct CentralDirectoryEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Writes a ZIP archive, each file is written to `writer` as soon as it's added.
+pub struct ZipWriter<W: Write> {
+    writer: W,
+    offset: u64,
+    entries: Vec<CentralDirectoryEntry>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            offset: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds a file to the archive, compressed only if deflate makes it smaller
+    /// as most image formats are already compressed.
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let deflated = encoder.finish()?;
+
+        let (method, content) = if deflated.len() < data.len() {
+            (METHOD_DEFLATED, deflated.as_slice())
+        } else {
+            (METHOD_STORED, data)
+        };
+
+        let entry = CentralDirectoryEntry {
+            name: name.to_owned(),
+            method,
+            crc: crc32fast::hash(data),
+            compressed_size: zip32(content.len() as u64)?,
+            size: zip32(data.len() as u64)?,
+            offset: zip32(self.offset)?,
+        };
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&LOCAL_FILE_HEADER_SIGNATURE.to_le_bytes());
+        header.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+        header.extend_from_slice(&UTF8_FLAG.to_le_bytes());
+        header.extend_from_slice(&entry.method.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&DOS_DATE.to_le_bytes());
+        header.extend_from_slice(&entry.crc.to_le_bytes());
+        header.extend_from_slice(&entry.compressed_size.to_le_bytes());
+        header.extend_from_slice(&entry.size.to_le_bytes());
+        header.extend_from_slice(&name_len(name)?.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+
+        self.write(&header)?;
+        self.write(content)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// The bytes written so far, without the central directory.
+    pub fn bytes_written(&self) -> u64 {
+        self.offset
+    }
+
+    /// Writes the central directory and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let entries = std::mem::take(&mut self.entries);
+        let count = u16::try_from(entries.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many files"))?;
+
+        let directory_offset = zip32(self.offset)?;
+
+        for entry in &entries {
+            let mut header = Vec::with_capacity(46 + entry.name.len());
+            header.extend_from_slice(&CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+            header.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+            header.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+            header.extend_from_slice(&UTF8_FLAG.to_le_bytes());
+            header.extend_from_slice(&entry.method.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&DOS_DATE.to_le_bytes());
+            header.extend_from_slice(&entry.crc.to_le_bytes());
+            header.extend_from_slice(&entry.compressed_size.to_le_bytes());
+            header.extend_from_slice(&entry.size.to_le_bytes());
+            header.extend_from_slice(&name_len(&entry.name)?.to_le_bytes());
+            // Extra field, comment, disk number, internal and external attributes
+            header.extend_from_slice(&[0; 12]);
+            header.extend_from_slice(&entry.offset.to_le_bytes());
+            header.extend_from_slice(entry.name.as_bytes());
+            self.write(&header)?;
+        }
+
+        let directory_size = zip32(self.offset - directory_offset as u64)?;
+
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&directory_size.to_le_bytes());
+        end.extend_from_slice(&directory_offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.write(&end)?;
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer.write_all(buf)?;
+        self.offset += buf.len() as u64;
+        Ok(())
+    }
+}
+
+/// ZIP64 isn't supported, sizes and offsets must fit in 32 bits.
+fn zip32(value: u64) -> io::Result<u32> {
+    u32::try_from(value)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the archive is too large"))
+}
+
+fn name_len(name: &str) -> io::Result<u16> {
+    u16::try_from(name.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the file name is too long"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::DeflateDecoder;
+    use std::io::Read;
+
+    struct Entry {
+        name: String,
+        method: u16,
+        data: Vec<u8>,
+    }
+
+    fn u16_at(buf: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(buf: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Reads the archive from the central directory, checking each local header against it.
+    fn read_zip(zip: &[u8]) -> Vec<Entry> {
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(zip, end), END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        assert_eq!(u16_at(zip, end + 20), 0, "no comment");
+
+        let count = u16_at(zip, end + 10) as usize;
+        assert_eq!(u16_at(zip, end + 8) as usize, count);
+        let directory_size = u32_at(zip, end + 12) as usize;
+        let mut pos = u32_at(zip, end + 16) as usize;
+        assert_eq!(pos + directory_size, end);
+
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            assert_eq!(u32_at(zip, pos), CENTRAL_DIRECTORY_SIGNATURE);
+            assert_eq!(u16_at(zip, pos + 8), UTF8_FLAG);
+            let method = u16_at(zip, pos + 10);
+            let crc = u32_at(zip, pos + 16);
+            let compressed_size = u32_at(zip, pos + 20) as usize;
+            let size = u32_at(zip, pos + 24) as usize;
+            let name_len = u16_at(zip, pos + 28) as usize;
+            let offset = u32_at(zip, pos + 42) as usize;
+            let name = &zip[pos + 46..pos + 46 + name_len];
+
+            // The local header repeats the fields of the central directory
+            assert_eq!(u32_at(zip, offset), LOCAL_FILE_HEADER_SIGNATURE);
+            assert_eq!(u16_at(zip, offset + 8), method);
+            assert_eq!(u32_at(zip, offset + 14), crc);
+            assert_eq!(u32_at(zip, offset + 18) as usize, compressed_size);
+            assert_eq!(u32_at(zip, offset + 22) as usize, size);
+            assert_eq!(u16_at(zip, offset + 26) as usize, name_len);
+            assert_eq!(u16_at(zip, offset + 28), 0, "no extra field");
+            assert_eq!(&zip[offset + 30..offset + 30 + name_len], name);
+
+            let content_at = offset + 30 + name_len;
+            let content = &zip[content_at..content_at + compressed_size];
+            let data = match method {
+                METHOD_STORED => content.to_vec(),
+                METHOD_DEFLATED => {
+                    let mut data = Vec::new();
+                    DeflateDecoder::new(content).read_to_end(&mut data).unwrap();
+                    data
+                }
+                method => panic!("unexpected method {method}"),
+            };
+
+            assert_eq!(data.len(), size);
+            assert_eq!(crc32fast::hash(&data), crc);
+
+            entries.push(Entry {
+                name: String::from_utf8(name.to_vec()).unwrap(),
+                method,
+                data,
+            });
+            pos += 46 + name_len;
+        }
+
+        entries
+    }
+
+    #[test]
+    fn round_trips_files() {
+        let text = "the same line, again and again\n".repeat(100).into_bytes();
+        // Pseudo-random bytes that deflate can't make smaller
+        let mut state = 0x2545_f491_u32;
+        let noise = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect::<Vec<_>>();
+
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add_file("notes.txt", &text).unwrap();
+        zip.add_file("images/photo.webp", &noise).unwrap();
+        zip.add_file("résumé.json", b"{}").unwrap();
+        zip.add_file("empty", b"").unwrap();
+        let written = zip.bytes_written();
+        let zip = zip.finish().unwrap();
+
+        assert!(written < zip.len() as u64);
+
+        let entries = read_zip(&zip);
+        let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["notes.txt", "images/photo.webp", "résumé.json", "empty"]
+        );
+
+        assert_eq!(entries[0].method, METHOD_DEFLATED);
+        assert_eq!(entries[0].data, text);
+        assert_eq!(entries[1].method, METHOD_STORED);
+        assert_eq!(entries[1].data, noise);
+        assert_eq!(entries[2].data, b"{}");
+        assert!(entries[3].data.is_empty());
+    }
+
+    #[test]
+    fn writes_an_empty_archive() {
+        let zip = ZipWriter::new(Vec::new()).finish().unwrap();
+
+        assert_eq!(zip.len(), 22);
+        assert!(read_zip(&zip).is_empty());
+    }
+}
@@ -14,6 +14,7 @@ pub type ResponseFuture = Pin<Box<dyn Future<Output = Response<Body>> + Send>>;
 pub struct ResponseError {
     message: String,
     status: StatusCode,
+    errors: Vec<String>,
 }
 
 impl ResponseError {
@@ -21,6 +22,7 @@ impl ResponseError {
         ResponseError {
             message: msg.into(),
             status,
+            errors: Vec::new(),
         }
     }
 
@@ -31,26 +33,49 @@ impl ResponseError {
         Self::new(StatusCode::INTERNAL_SERVER_ERROR, error.into().to_string())
     }
 
-    pub fn with_status(self, status: StatusCode) -> Self {
-        Self {
-            status,
-            message: self.message,
+    /// Returns the `ResponseError` inside a handler error, other errors are internal server errors.
+    pub fn from_boxed(error: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        match error.downcast::<ResponseError>() {
+            Ok(error) => *error,
+            Err(error) => Self::from_error(error),
         }
     }
 
+    pub fn with_status(self, status: StatusCode) -> Self {
+        Self { status, ..self }
+    }
+
+    pub fn with_errors(self, errors: Vec<String>) -> Self {
+        Self { errors, ..self }
+    }
+
     pub fn status(&self) -> StatusCode {
         self.status
     }
 
     pub fn message(&self) -> &str {
-        &self.message.as_str()
+        self.message.as_str()
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    /// The body of the error response, with the `errors` only if there are any.
+    pub fn to_json(&self) -> serde_json::Value {
+        if self.errors.is_empty() {
+            serde_json::json!({ "message": self.message })
+        } else {
+            serde_json::json!({ "message": self.message, "errors": self.errors })
+        }
     }
 }
 
 impl IntoResponse for ResponseError {
     fn into_response(self) -> ResponseFuture {
-        let ResponseError { message, status } = self;
-        let msg = serde_json::json!({ "message": message });
+        let msg = self.to_json();
+        let status = self.status;
+
         let json = serde_json::to_string(&msg).expect("failed to convert message to JSON");
         let body = Body::Text(json);
 
@@ -1,36 +1,349 @@
+use crate::common::InputFormat;
 use crate::error::ResponseError;
-use base64::Engine as _;
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine as _,
+};
 use http::StatusCode;
-use image::ImageFormat;
 use lambda_runtime::Error;
-use once_cell::sync::Lazy;
-use regex::Regex;
 
-#[tracing::instrument(level = "INFO")]
-pub async fn get_image_from_base64(base64_text: String) -> Result<(Vec<u8>, ImageFormat), Error> {
-    static ERROR_MSG : &str = "failed to get base64 data, expected format: data:image/type;base64,ABCDEFGHIJKLMNOPQRStuvwxyz";
-    static DATA_IMAGE_REGEX: Lazy<Regex> = Lazy::new(|| {
-        Regex::new(r"data:image/(?P<type>\w+);base64,(?P<data>[a-zA-Z0-9+/=]+)").expect("failed to build regex")
-    });
+/// Decodes with or without the `=` padding.
+const PADDING_INDIFFERENT: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
 
-    let captures = DATA_IMAGE_REGEX
-        .captures(&base64_text)
-        .ok_or_else(|| ResponseError::new(StatusCode::BAD_REQUEST, ERROR_MSG))?;
+const STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, PADDING_INDIFFERENT);
+const URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, PADDING_INDIFFERENT);
 
-    let image_type = captures
-        .name("type")
-        .ok_or_else(|| ResponseError::new(StatusCode::BAD_REQUEST, ERROR_MSG))?
-        .as_str();
+/// Returns the image of a data URL like `data:image/png;base64,iVBORw0KG...` or of raw base64 data.
+///
+/// The format is detected from the content, the media type of the data URL is only used
+/// if it can't be detected.
+#[tracing::instrument(level = "INFO", skip(base64_text))]
+pub async fn get_image_from_base64(base64_text: String) -> Result<(Vec<u8>, InputFormat), Error> {
+    let text = base64_text.trim();
 
-    let data = captures
-        .name("data")
-        .ok_or_else(|| ResponseError::new(StatusCode::BAD_REQUEST, ERROR_MSG))?
-        .as_str();
+    let (buffer, media_type) = match strip_prefix_ignore_case(text, "data:") {
+        Some(data_url) => {
+            let data_url = DataUrl::parse(data_url)?;
+            (data_url.decode()?, data_url.media_type)
+        }
+        None => (decode_base64(text)?, None),
+    };
 
-    let format = ImageFormat::from_extension(image_type)
-        .ok_or_else(|| Error::from("failed to read format"))?;
+    if buffer.is_empty() {
+        return Err(bad_request("the image data is empty"));
+    }
 
-    let buffer = base64::engine::general_purpose::STANDARD.decode(data)?;
+    let format = InputFormat::from_bytes(&buffer)
+        .or_else(|| media_type.and_then(InputFormat::from_mime_type))
+        .ok_or_else(|| match media_type {
+            Some(media_type) => bad_request(format!(
+                "unable to detect the image format, the media type `{media_type}` is not supported"
+            )),
+            None => bad_request("unable to detect the image format of the base64 data"),
+        })?;
 
     Ok((buffer, format))
 }
+
+/// A data URL as defined in RFC 2397: `data:[<media type>][;base64],<data>`.
+struct DataUrl<'a> {
+    media_type: Option<&'a str>,
+    base64: bool,
+    data: &'a str,
+}
+
+impl<'a> DataUrl<'a> {
+    /// Parses a data URL without the `data:` scheme.
+    fn parse(data_url: &'a str) -> Result<Self, Error> {
+        let (header, data) = data_url
+            .split_once(',')
+            .ok_or_else(|| bad_request("invalid data URL, missing the `,` before the data"))?;
+
+        let mut params = header.split(';').map(str::trim).collect::<Vec<_>>();
+
+        // `base64` is always the last parameter
+        let base64 = params
+            .last()
+            .is_some_and(|param| param.eq_ignore_ascii_case("base64"));
+
+        if base64 {
+            params.pop();
+        }
+
+        // The parameters like `charset=utf-8` or `name=photo.png` are ignored
+        let media_type = match params.first().copied().unwrap_or_default() {
+            "" => None,
+            media_type => match media_type.split_once('/') {
+                Some((type_, subtype)) if !type_.is_empty() && !subtype.is_empty() => {
+                    Some(media_type)
+                }
+                _ => {
+                    return Err(bad_request(format!(
+                        "invalid media type `{media_type}` in the data URL, expected `type/subtype`"
+                    )))
+                }
+            },
+        };
+
+        if let Some(param) = params.iter().skip(1).find(|param| !param.contains('=')) {
+            return Err(bad_request(format!(
+                "invalid parameter `{param}` in the data URL, expected `name=value` or `base64` before the `,`"
+            )));
+        }
+
+        Ok(Self {
+            media_type,
+            base64,
+            data,
+        })
+    }
+
+    fn decode(&self) -> Result<Vec<u8>, Error> {
+        let data = match self.data.contains('%') {
+            true => percent_decode(self.data)?,
+            false => self.data.as_bytes().to_vec(),
+        };
+
+        match self.base64 {
+            true => decode_base64(&String::from_utf8_lossy(&data)),
+            // A data URL without `;base64` contains the percent encoded bytes, like an SVG
+            false => Ok(data),
+        }
+    }
+}
+
+/// Decodes the standard or the URL-safe alphabet, with or without padding, ignoring whitespace.
+fn decode_base64(text: &str) -> Result<Vec<u8>, Error> {
+    let data = text
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect::<String>();
+
+    if data.is_empty() {
+        return Err(bad_request("the base64 data is empty"));
+    }
+
+    let standard = data.contains(['+', '/']);
+    let url_safe = data.contains(['-', '_']);
+    if standard && url_safe {
+        return Err(bad_request(
+            "invalid base64 data, it mixes the standard (`+` and `/`) and the URL-safe (`-` and `_`) alphabets",
+        ));
+    }
+
+    let engine = match url_safe {
+        true => URL_SAFE,
+        false => STANDARD,
+    };
+
+    engine
+        .decode(&data)
+        .map_err(|e| bad_request(format!("invalid base64 data: {e}")))
+}
+
+fn percent_decode(text: &str) -> Result<Vec<u8>, Error> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] != b'%' {
+            decoded.push(bytes[index]);
+            index += 1;
+            continue;
+        }
+
+        let byte = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| {
+                bad_request(format!(
+                    "invalid percent encoding at offset {index} of the data URL"
+                ))
+            })?;
+
+        decoded.push(byte);
+        index += 3;
+    }
+
+    Ok(decoded)
+}
+
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let start = text.get(..prefix.len())?;
+    start
+        .eq_ignore_ascii_case(prefix)
+        .then(|| &text[prefix.len()..])
+}
+
+fn bad_request(message: impl Into<String>) -> Error {
+    ResponseError::new(StatusCode::BAD_REQUEST, message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose;
+    use image::{ImageFormat, ImageOutputFormat, RgbaImage};
+    use std::io::Cursor;
+
+    const SVG: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="4"/>"#;
+
+    fn png() -> Vec<u8> {
+        let mut buf = Vec::new();
+        RgbaImage::from_fn(7, 5, |x, y| {
+            image::Rgba([x as u8 * 30, y as u8 * 50, 90, 200])
+        })
+        .write_to(&mut Cursor::new(&mut buf), ImageOutputFormat::Png)
+        .unwrap();
+        buf
+    }
+
+    async fn decode(text: &str) -> Result<(Vec<u8>, InputFormat), Error> {
+        get_image_from_base64(text.to_owned()).await
+    }
+
+    async fn assert_png(text: &str) {
+        let (buffer, format) = decode(text).await.unwrap();
+        assert_eq!(buffer, png());
+        assert_eq!(format, InputFormat::Image(ImageFormat::Png));
+    }
+
+    async fn assert_bad_request(text: &str) {
+        let err = decode(text).await.unwrap_err();
+        assert_eq!(
+            ResponseError::from_boxed(err).status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    /// Splits the text in lines like MIME encoders do.
+    fn wrap(text: &str) -> String {
+        let chars: Vec<_> = text.chars().collect();
+        chars
+            .chunks(20)
+            .map(|line| line.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\r\n ")
+    }
+
+    #[tokio::test]
+    async fn decodes_data_urls() {
+        let base64 = general_purpose::STANDARD.encode(png());
+
+        assert_png(&format!("data:image/png;base64,{base64}")).await;
+        assert_png(&format!("DATA:Image/PNG;BASE64,{base64}")).await;
+        assert_png(&format!("data:;base64,{base64}")).await;
+        assert_png(&format!("data:image/png;name=logo.png;base64,{base64}")).await;
+        // The format is detected from the content, not from the media type
+        assert_png(&format!("data:image/jpeg;base64,{base64}")).await;
+    }
+
+    #[tokio::test]
+    async fn decodes_raw_base64() {
+        assert_png(&general_purpose::STANDARD.encode(png())).await;
+    }
+
+    #[tokio::test]
+    async fn decodes_url_safe_base64() {
+        let base64 = general_purpose::URL_SAFE.encode(png());
+        assert!(base64.contains(['-', '_']));
+
+        assert_png(&base64).await;
+        assert_png(&format!("data:image/png;base64,{base64}")).await;
+    }
+
+    #[tokio::test]
+    async fn decodes_unpadded_base64() {
+        let base64 = general_purpose::STANDARD_NO_PAD.encode(png());
+        assert_ne!(base64.len() % 4, 0);
+
+        assert_png(&base64).await;
+        assert_png(&general_purpose::URL_SAFE_NO_PAD.encode(png())).await;
+    }
+
+    #[tokio::test]
+    async fn ignores_whitespace() {
+        let base64 = wrap(&general_purpose::STANDARD.encode(png()));
+
+        assert_png(&format!("\n  {base64}\n")).await;
+        assert_png(&format!(" data:image/png;base64,{base64} ")).await;
+    }
+
+    #[tokio::test]
+    async fn decodes_percent_encoded_base64() {
+        let base64 = general_purpose::STANDARD
+            .encode(png())
+            .replace('+', "%2B")
+            .replace('/', "%2F")
+            .replace('=', "%3D");
+
+        assert_png(&format!("data:image/png;base64,{base64}")).await;
+    }
+
+    #[tokio::test]
+    async fn decodes_percent_encoded_svg() {
+        let encoded = SVG
+            .replace('%', "%25")
+            .replace('<', "%3C")
+            .replace('>', "%3E")
+            .replace('"', "%22")
+            .replace(' ', "%20");
+
+        let (buffer, format) = decode(&format!("data:image/svg+xml,{encoded}"))
+            .await
+            .unwrap();
+        assert_eq!(buffer, SVG.as_bytes());
+        assert_eq!(format, InputFormat::Svg);
+
+        let (buffer, format) = decode(&format!("data:image/svg+xml;charset=utf-8,{SVG}"))
+            .await
+            .unwrap();
+        assert_eq!(buffer, SVG.as_bytes());
+        assert_eq!(format, InputFormat::Svg);
+    }
+
+    #[tokio::test]
+    async fn uses_the_media_type_if_the_format_is_not_detected() {
+        let (_, format) = decode("data:image/heic;base64,AAAA").await.unwrap();
+        assert_eq!(format, InputFormat::Heif);
+
+        assert_bad_request("data:text/plain;base64,aGVsbG8=").await;
+        assert_bad_request("aGVsbG8=").await;
+    }
+
+    #[tokio::test]
+    async fn rejects_trailing_garbage() {
+        let base64 = general_purpose::STANDARD.encode(png());
+
+        assert_bad_request(&format!("{base64}!!")).await;
+        assert_bad_request(&format!("{base64}AAAAA")).await;
+        assert_bad_request(&format!("data:image/png;base64,{base64}#fragment")).await;
+    }
+
+    #[tokio::test]
+    async fn rejects_mixed_alphabets() {
+        assert_bad_request("ab+c-d/e_fgh").await;
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_data_urls() {
+        assert_bad_request("data:image/png;base64").await;
+        assert_bad_request("data:png;base64,AAAA").await;
+        assert_bad_request("data:image/png;utf8;base64,AAAA").await;
+        assert_bad_request("data:image/svg+xml,%3Csvg%G0").await;
+        assert_bad_request("data:image/svg+xml,%3").await;
+    }
+
+    #[tokio::test]
+    async fn rejects_empty_payloads() {
+        assert_bad_request("").await;
+        assert_bad_request(" \n ").await;
+        assert_bad_request("data:image/png;base64,").await;
+        assert_bad_request("data:image/png;base64, \n").await;
+        assert_bad_request("data:image/svg+xml,").await;
+    }
+}
@@ -3,3 +3,6 @@ pub mod lambda_helper;
 
 mod base64_image;
 pub use base64_image::get_image_from_base64;
+
+mod url_image;
+pub use url_image::get_image_from_url;
@@ -0,0 +1,244 @@
+use crate::common::InputFormat;
+use crate::error::ResponseError;
+use lambda_runtime::Error;
+use reqwest::{header, redirect, Client, Response, StatusCode, Url};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// The maximum time to download an image, including the connection and the redirects.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The maximum size of a downloaded image.
+const MAX_FETCH_BYTES: usize = 20 * 1024 * 1024;
+
+/// The maximum number of redirects followed, each one is checked like the URL of the request.
+const MAX_REDIRECTS: usize = 5;
+
+/// Downloads an image, the format is read from the content type or detected from the content.
+///
+/// Only public addresses can be fetched, the host of the URL and of each redirect is resolved
+/// and the connection is made to the checked address so the server can't reach internal services.
+///
+/// An invalid URL, a private address or a too large image is a `400`, a failed download is a `502`.
+#[tracing::instrument(level = "INFO")]
+pub async fn get_image_from_url(url: String) -> Result<(Vec<u8>, InputFormat), Error> {
+    let parsed_url =
+        Url::parse(&url).map_err(|e| bad_request(format!("invalid `source_url` {url}: {e}")))?;
+
+    let mut res = fetch(parsed_url, &url).await?;
+
+    if !res.status().is_success() {
+        return Err(ResponseError::new(
+            StatusCode::BAD_GATEWAY,
+            format!(
+                "failed to get the image from {url}, the server returned {}",
+                res.status()
+            ),
+        )
+        .into());
+    }
+
+    if res
+        .content_length()
+        .is_some_and(|len| len > MAX_FETCH_BYTES as u64)
+    {
+        return Err(too_large(&url));
+    }
+
+    let content_type = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map(str::to_owned);
+
+    // The length header is optional, so the body is also checked while it's read
+    let mut buffer = Vec::new();
+    while let Some(chunk) = res.chunk().await.map_err(|e| bad_gateway(&url, e))? {
+        if buffer.len() + chunk.len() > MAX_FETCH_BYTES {
+            return Err(too_large(&url));
+        }
+
+        buffer.extend_from_slice(&chunk);
+    }
+
+    let format = content_type
+        .as_deref()
+        .and_then(InputFormat::from_mime_type)
+        .or_else(|| InputFormat::from_bytes(&buffer))
+        .ok_or_else(|| bad_request(format!("unable to detect the image format of {url}")))?;
+
+    Ok((buffer, format))
+}
+
+/// Sends the request and follows the redirects, checking the address of each URL.
+async fn fetch(mut url: Url, source_url: &str) -> Result<Response, Error> {
+    let start = Instant::now();
+
+    for _ in 0..=MAX_REDIRECTS {
+        let addr = resolve_public_addr(&url, source_url).await?;
+        let timeout = FETCH_TIMEOUT.saturating_sub(start.elapsed());
+
+        // The client only connects to the checked address, a second lookup could return another one
+        let mut builder = Client::builder()
+            .timeout(timeout)
+            .redirect(redirect::Policy::none());
+        if let Some(domain) = url.domain() {
+            builder = builder.resolve(domain, addr);
+        }
+
+        let client = builder.build().map_err(ResponseError::from_error)?;
+        let res = client
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|e| bad_gateway(source_url, e))?;
+
+        if !res.status().is_redirection() {
+            return Ok(res);
+        }
+
+        let location = res
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or_else(|| {
+                ResponseError::new(
+                    StatusCode::BAD_GATEWAY,
+                    format!("failed to get the image from {source_url}, the server returned a redirect without location"),
+                )
+            })?;
+
+        url = url.join(location).map_err(|e| {
+            ResponseError::new(
+                StatusCode::BAD_GATEWAY,
+                format!("failed to get the image from {source_url}, invalid redirect to {location}: {e}"),
+            )
+        })?;
+    }
+
+    Err(ResponseError::new(
+        StatusCode::BAD_GATEWAY,
+        format!("failed to get the image from {source_url}, more than {MAX_REDIRECTS} redirects"),
+    )
+    .into())
+}
+
+/// Resolves the host of an http or https URL, all of its addresses must be public.
+async fn resolve_public_addr(url: &Url, source_url: &str) -> Result<SocketAddr, Error> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(bad_request(format!(
+            "invalid `source_url` {source_url}, expected an http or https URL but got {url}"
+        )));
+    }
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| bad_request(format!("invalid `source_url` {source_url}, missing host")))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    // IPv6 literals keep the brackets in `host_str`
+    let addrs = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| {
+                ResponseError::new(
+                    StatusCode::BAD_GATEWAY,
+                    format!(
+                        "failed to get the image from {source_url}, unable to resolve {host}: {e}"
+                    ),
+                )
+            })?
+            .collect(),
+    };
+
+    if let Some(addr) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+        return Err(bad_request(format!(
+            "invalid `source_url` {source_url}, {} is not a public address",
+            addr.ip()
+        )));
+    }
+
+    addrs.first().copied().ok_or_else(|| {
+        ResponseError::new(
+            StatusCode::BAD_GATEWAY,
+            format!("failed to get the image from {source_url}, {host} has no address"),
+        )
+        .into()
+    })
+}
+
+/// Whether the address can be reached from the internet, the loopback, private,
+/// link-local (like the `169.254.169.254` metadata service) and reserved ranges are not.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "This network", shared address space (CGNAT), IETF protocol assignments,
+        // benchmarking and reserved
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    // IPv4-mapped (`::ffff:127.0.0.1`) and NAT64 (`64:ff9b::7f00:1`) addresses reach IPv4 hosts
+    if let Some(ipv4) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(ipv4);
+    }
+
+    let segments = ip.segments();
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_ipv4(Ipv4Addr::new(a, b, c, d));
+    }
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, link-local, site-local and documentation
+        || (segments[0] & 0xfe00) == 0xfc00
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] & 0xffc0) == 0xfec0
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+fn bad_request(message: String) -> Error {
+    ResponseError::new(StatusCode::BAD_REQUEST, message).into()
+}
+
+fn bad_gateway(url: &str, err: reqwest::Error) -> Error {
+    let reason = match err.is_timeout() {
+        true => format!("timed out after {} seconds", FETCH_TIMEOUT.as_secs()),
+        false => err.to_string(),
+    };
+
+    ResponseError::new(
+        StatusCode::BAD_GATEWAY,
+        format!("failed to get the image from {url}, {reason}"),
+    )
+    .into()
+}
+
+fn too_large(url: &str) -> Error {
+    bad_request(format!(
+        "the image at {url} is larger than {} MB",
+        MAX_FETCH_BYTES / 1024 / 1024
+    ))
+}